@@ -0,0 +1,106 @@
+// Times parsing the bundled log against the regex cascade it replaced: `cargo bench`.
+use std::fs;
+use std::hint::black_box;
+use std::io::{BufRead, Cursor};
+use std::time::{Duration, Instant};
+
+use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use log_grapher::events::LogReader;
+
+const ITERATIONS: u32 = 50;
+
+lazy_static! {
+    // The regexes the parser used to try on each line in turn, in the same order.
+    static ref CASCADE: Vec<Regex> = [
+        r#"^"(?P<attacker>.+?)" triggered "damage" against "(?P<victim>.+?)"[\s|$]"#,
+        r#"^"(?P<player>.+?)" triggered "healed" against "(?P<target>.+?)"[\s|$]"#,
+        r#"^"(?P<player>.+?)" triggered "shot_fired""#,
+        r#"^"(?P<player>.+?)" triggered "shot_hit""#,
+        r#"^"(?P<attacker>.+?)" killed "(?P<victim>.+?)" with "(?P<weapon>.+?)""#,
+        r#"^"(?P<player>.+?)" changed role to "(?P<role>.+?)""#,
+        r#"^"(?P<player>.+?)" spawned as "(?P<role>.+?)""#,
+        r#"^"(?P<attacker>.+?)" triggered "medic_death" against "(?P<victim>.+?)"[\s|$]"#,
+        r#"^"(?P<player>.+?)" say "(?P<message>.{1,160}?)"$"#,
+        r#"^Team "(?P<team>Red|Blue)" triggered "pointcaptured""#,
+        r#"^"(?P<player>.+?)" triggered "chargedeployed""#,
+        r#"^World triggered "Round_Start""#,
+        r#"^World triggered "Round_Win" \(winner "(?P<winner>Red|Blue)"\)"#,
+        r#"^World triggered "Round_Overtime""#,
+        r#"^World triggered "Game_Over""#,
+    ]
+    .iter()
+    .map(|r| Regex::new(r).unwrap())
+    .collect();
+    static ref PROPERTIES: Regex = Regex::new(r#"\((\w{1,60}) "([^"]{1,60})"\)"#).unwrap();
+    static ref PLAYER: Regex = Regex::new(r#"^(?P<name>.{1,80}?)<\d{1,4}><(?P<steamid>.{1,40})><(?P<team>Red|Blue|Spectator|Unassigned|Console|unknown|)>"#).unwrap();
+}
+
+// The old parser's work for each line, short of building the events: the timestamp, the first regex that matches,
+// its properties and the players in it. Returns how many lines were events.
+fn regex_cascade(log: &[u8]) -> usize {
+    let mut players: Vec<String> = vec![];
+    let mut events = 0;
+    for line in Cursor::new(log).lines() {
+        let line = line.unwrap();
+        let timestamp = match line.get(2..23).and_then(|dt| NaiveDateTime::parse_from_str(dt, "%m/%d/%Y - %H:%M:%S").ok()) {
+            Some(dt) => dt.and_utc().timestamp(),
+            None => continue,
+        };
+        black_box(timestamp);
+        let l = match line.get(25..) {
+            Some(l) => l,
+            None => continue,
+        };
+
+        let captures = match CASCADE.iter().find_map(|regex| regex.captures(l)) {
+            Some(captures) => captures,
+            None => continue,
+        };
+        let end = captures.get(0).unwrap().end();
+        for property in PROPERTIES.captures_iter(&l[end..]) {
+            black_box((&property[1], &property[2]));
+        }
+        for name in ["attacker", "victim", "player", "target"] {
+            if let Some(slice) = captures.name(name).map(|m| m.as_str()) {
+                if !players.iter().any(|id| slice.contains(id.as_str())) {
+                    if let Some(player) = PLAYER.captures(slice) {
+                        players.push(player["steamid"].to_owned());
+                    }
+                }
+            }
+        }
+        events += 1;
+    }
+    events
+}
+
+fn time(name: &str, log: &[u8], parse: impl Fn(&[u8]) -> usize) -> Duration {
+    let mut times = vec![];
+    let mut events = 0;
+    for _ in 0..ITERATIONS {
+        let before = Instant::now();
+        events = parse(log);
+        times.push(before.elapsed());
+    }
+
+    times.sort();
+    let mean = times.iter().sum::<Duration>() / ITERATIONS;
+    let mb_per_second = log.len() as f64 / 1_000_000.0 / mean.as_secs_f64();
+    println!(
+        "{:<14}{} events, mean {:.2?}, min {:.2?}, max {:.2?} ({:.0} MB/s)",
+        name, events, mean, times[0], times[times.len() - 1], mb_per_second,
+    );
+    mean
+}
+
+fn main() {
+    let log = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/l0819011.log")).expect("Failed to read l0819011.log.");
+
+    println!("l0819011.log, {} KB:", log.len() / 1000);
+    let regex = time("regex", &log, regex_cascade);
+    let parser = time("parser", &log, |log| LogReader::new(Cursor::new(log)).filter_map(Result::ok).count());
+    println!("{:.1}x faster than the regex cascade", regex.as_secs_f64() / parser.as_secs_f64());
+}
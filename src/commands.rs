@@ -0,0 +1,1620 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::UdpSocket;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use getopts::{Matches, Options};
+use serde_json::json;
+
+use crate::download::{cache_dir, cache_entries, log_from_download, log_from_file, meta_from_download, search_logs, LogMeta, LogsTf};
+use crate::draw::*;
+use crate::error::Error;
+use crate::events::*;
+use crate::html::*;
+use crate::live::*;
+use crate::mode::*;
+use crate::summary::*;
+use crate::theme::*;
+use crate::{Class, Player, Team};
+
+const DEFAULT_BATCHING: i64 = 10;
+
+enum OutputFormat {
+    Png,
+    Html,
+}
+
+// Options every command takes.
+pub fn input_options(opts: &mut Options) {
+    opts
+        .optflag("h", "help", "print this help menu")
+        .optopt("", "log-id", "download and process a log given an id", "LOGID")
+        .optopt("", "log-file", "process a log file from disk (plain, zip, gzip or zstd), or - for stdin", "FILE")
+        .optopt("", "match", "which match in the log to use, from 1, defaults to the first one played to the end", "N");
+    download_options(opts);
+}
+
+fn download_options(opts: &mut Options) {
+    opts
+        .optflag("", "offline", "only use logs that have already been downloaded")
+        .optflag("", "meta", "also fetch the log's title, map, uploader and player names from logs.tf")
+        .optopt("", "base-url", "where to download logs from, instead of https://logs.tf", "URL");
+}
+
+fn player_options(opts: &mut Options) {
+    opts
+        .optopt("", "steamid", "the SteamID3 of the player", "STEAMID3")
+        .optopt("", "steamids", "a comma separated list of players", "STEAMID3_1, STEAMID3_2, ..")
+        .optopt("", "alias", "the alias of the player, or the start of it", "ALIAS");
+}
+
+fn batching_option(opts: &mut Options) {
+    opts.optopt("", "batching", "the batching period of events", "SECONDS");
+}
+
+fn style_options(opts: &mut Options) {
+    opts
+        .optopt("", "theme", "a built in theme (dark, light, colorblind, courtney) or a theme file", "NAME|FILE")
+        .optopt("", "width", "the width of the graph in pixels", "PIXELS")
+        .optopt("", "height", "the height of the graph in pixels", "PIXELS")
+        .optopt("", "scale", "scale text, lines and markers, e.g. 2 for high-DPI screens", "FACTOR")
+        .optopt("", "font", "a font file to draw text with, instead of the system monospace font", "PATH")
+        .optflag("", "chat", "mark chat messages on the graph");
+}
+
+pub fn graph_options(opts: &mut Options) {
+    player_options(opts);
+    batching_option(opts);
+    style_options(opts);
+    opts
+        .optflag("", "overlay", "draw the players given by --steamids on a single graph")
+        .optopt("", "team", "graph a whole team, or the momentum between both", "red|blue|both")
+        .optopt("", "format", "the output format, png (default) or html", "FORMAT")
+        .optopt("", "player-history", "graph the player's most recent matches on logs.tf, and how they've trended", "STEAMID")
+        .optopt("", "last", "how many matches --player-history graphs, defaults to 5", "N")
+        .optopt("", "output", "where to write the graph, with {logid}, {name}, {steamid} and {steamid64} filled in", "TEMPLATE")
+        .optopt("", "output-dir", "the directory to write graphs to", "DIR")
+        .optflag("", "force", "overwrite existing files");
+}
+
+pub fn summary_options(opts: &mut Options) {
+    opts.optopt("", "round", "only summarise the given round, starting from 1", "N");
+}
+
+pub fn highlights_options(opts: &mut Options) {
+    player_options(opts);
+    batching_option(opts);
+    opts.optopt("", "team", "highlights for a whole team", "red|blue");
+}
+
+pub fn players_options(opts: &mut Options) {
+    opts.optflag("", "json", "print the players as json");
+}
+
+pub fn export_options(opts: &mut Options) {
+    player_options(opts);
+    batching_option(opts);
+    opts
+        .optflag("", "chat", "export the chat instead, everyone's unless players are given")
+        .optopt("", "format", "csv (default) for each player's graph data, or json or ndjson for everything that was parsed", "FORMAT");
+}
+
+// `cache` doesn't take a log, so it doesn't get input_options.
+pub fn cache_options(opts: &mut Options) {
+    opts
+        .optflag("h", "help", "print this help menu")
+        .optflag("", "prune", "remove cached downloads, all of them unless --older-than or --max-size is given")
+        .optopt("", "older-than", "only prune downloads older than this many days", "DAYS")
+        .optopt("", "max-size", "prune the oldest downloads until the cache is under this size", "MB");
+}
+
+// `listen` gets its log from the server rather than --log-id or --log-file.
+pub fn listen_options(opts: &mut Options) {
+    opts.optflag("h", "help", "print this help menu");
+    batching_option(opts);
+    style_options(opts);
+    opts
+        .optopt("", "bind", "the address to listen on, defaults to 0.0.0.0:27500", "ADDRESS")
+        .optopt("", "secret", "only accept logs sent with this sv_logsecret", "SECRET")
+        .optopt("", "steamids", "a comma separated list of players to graph", "STEAMID3_1, STEAMID3_2, ..")
+        .optopt("", "output-dir", "the directory to write everything to, defaults to live", "DIR")
+        .optflag("", "once", "stop once the first match is over");
+}
+
+// `watch` reads the logs the server writes rather than --log-id or --log-file.
+pub fn watch_options(opts: &mut Options) {
+    opts.optflag("h", "help", "print this help menu");
+    batching_option(opts);
+    style_options(opts);
+    opts
+        .optopt("", "steamids", "a comma separated list of players to graph", "STEAMID3_1, STEAMID3_2, ..")
+        .optopt("", "output-dir", "the directory to write each match to, defaults to matches", "DIR")
+        .optflag("", "force", "overwrite existing files")
+        .optflag("", "once", "stop once the first match is over");
+}
+
+// `batch` takes any number of --log-id and --log-file, so it doesn't get input_options either.
+pub fn batch_options(opts: &mut Options) {
+    opts
+        .optflag("h", "help", "print this help menu")
+        .optmulti("", "log-id", "download and process a log given an id, can be given more than once", "LOGID")
+        .optmulti("", "log-file", "process a log file from disk, can be given more than once", "FILE")
+        .optopt("", "match", "which match in each log to use, from 1, defaults to the first one played to the end", "N");
+    download_options(opts);
+    batching_option(opts);
+    style_options(opts);
+    opts
+        .optopt("", "list", "a file of log ids, log files or directories to process, one per line", "FILE")
+        .optopt("", "steamids", "a comma separated list of players to graph in each log they played in", "STEAMID3_1, STEAMID3_2, ..")
+        .optopt("", "jobs", "how many logs to process at once, defaults to the number of cores", "N")
+        .optopt("", "output-dir", "the directory to write everything to, defaults to batch", "DIR")
+        .optflag("", "force", "overwrite existing files");
+}
+
+// Everything parsed out of a log, shared by all commands.
+pub struct ParsedLog {
+    // The log's id on logs.tf, or the name of the file it came from.
+    pub id: String,
+    pub lines: usize,
+    pub players: Vec<Player>,
+    pub events: Vec<Event>,
+    pub rounds: Vec<Round>,
+    pub filtered: Vec<FilteredEvents>,
+    pub info: LogInfo,
+    // From logs.tf, with --meta.
+    pub meta: Option<LogMeta>,
+    // Every match in the log, and the one `events` is from.
+    pub matches: Vec<Match>,
+    pub selected: usize,
+    // What was played in the selected match, worked out from the log.
+    pub format: Format,
+    pub mode: GameMode,
+}
+
+// Where a log comes from.
+#[derive(Debug, Clone)]
+pub enum LogSource {
+    Id(String),
+    File(String),
+}
+
+impl LogSource {
+    fn from_matches(matches: &Matches) -> Result<Self, Error> {
+        if let Some(log_id) = matches.opt_str("log-id") {
+            Ok(LogSource::Id(log_id))
+        } else if let Some(log_file) = matches.opt_str("log-file") {
+            Ok(LogSource::File(log_file))
+        } else {
+            Err(Error::usage("One of either --log-id or --log-file is required."))
+        }
+    }
+
+    // The log's id on logs.tf, or the name of the file it came from.
+    pub fn id(&self) -> String {
+        match self {
+            LogSource::Id(log_id) => log_id.clone(),
+            LogSource::File(file) => Path::new(file).file_stem().map_or(file.clone(), |s| s.to_string_lossy().into_owned()),
+        }
+    }
+
+    // For error messages.
+    pub fn name(&self) -> String {
+        match self {
+            LogSource::Id(log_id) => format!("log {}", log_id),
+            LogSource::File(file) => file.clone(),
+        }
+    }
+}
+
+impl ParsedLog {
+    pub fn from_matches(matches: &Matches) -> Result<Self, Error> {
+        let before = Instant::now();
+        let log = Self::load(&LogSource::from_matches(matches)?, &logs_tf(matches), match_number(matches)?)?;
+        eprintln!("Processed log in {:.2?}", before.elapsed());
+        Ok(log)
+    }
+
+    pub fn load(source: &LogSource, logs_tf: &LogsTf, selected: Option<usize>) -> Result<Self, Error> {
+        let input = source.name();
+        let reader = match source {
+            LogSource::Id(log_id) => log_from_download(logs_tf, log_id),
+            LogSource::File(file) => log_from_file(file),
+        }.map_err(|e| Error::input(&input, e))?;
+
+        // Only the selected match is kept as the log is read, however many there are.
+        let mut matches = MatchSplitter::new(selected);
+        let (players, info, lines) = read_log(reader, |event, _| matches.push(event)).map_err(|e| Error::input(&input, e))?;
+
+        // The metadata is nice to have, so the log is still used without it.
+        let meta = match source {
+            LogSource::Id(log_id) if logs_tf.meta => meta_from_download(logs_tf, log_id)
+                .map_err(|e| eprintln!("Couldn't fetch the metadata for {}: {}", input, e))
+                .ok(),
+            _ => None,
+        };
+
+        Self::new(source.id(), &input, lines, players, matches, info, meta, selected)
+    }
+
+    // A log that's already been read, e.g. one that's still being written. `input` is what it's called in errors.
+    // `selected` is the match to use, from 0, otherwise the first one that was played to the end (or the last one if none were).
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: String,
+        input: &str,
+        lines: usize,
+        mut players: Vec<Player>,
+        splitter: MatchSplitter,
+        info: LogInfo,
+        meta: Option<LogMeta>,
+        selected: Option<usize>,
+    ) -> Result<Self, Error> {
+        if splitter.count == 0 {
+            return Err(Error::input(input, "no events found, is it a log file?"));
+        }
+        let (matches, kept) = splitter.finish();
+        if matches.is_empty() {
+            return Err(Error::input(input, "no rounds were played"));
+        }
+        let (selected, events) = match (selected, kept) {
+            (_, Some(kept)) => kept,
+            (Some(_), None) => return Err(Error::usage(format!("The log only has {} matches.", matches.len()))),
+            (None, None) => unreachable!("a match is always kept when none was selected"),
+        };
+        if let Some(meta) = &meta {
+            use_official_names(&mut players, meta);
+        }
+
+        let rounds = find_rounds(&events);
+        let filtered = filter_events(&players, &events)?;
+        let format = detect_format(&filtered, &events);
+        let map = meta.as_ref().map(|meta| meta.map.as_str()).filter(|map| !map.is_empty()).or(info.map.as_deref());
+        let mode = detect_mode(map, &events);
+
+        Ok(Self { id, lines, players, events, rounds, filtered, info, meta, matches, selected, format, mode })
+    }
+
+    // What the log is, for graph captions: its title on logs.tf if it was fetched, otherwise what the server logged.
+    pub fn caption(&self) -> Option<String> {
+        let caption = match &self.meta {
+            Some(meta) => meta.describe(),
+            None => self.info.describe(),
+        };
+        Some(caption).filter(|c| !c.is_empty())
+    }
+
+    // From the start of the first round to the end of the last, or the whole log if there weren't any.
+    pub fn match_range(&self) -> (i64, i64) {
+        match (self.rounds.first(), self.rounds.last()) {
+            (Some(first), Some(last)) => (first.start, last.end),
+            _ => (
+                self.events.first().map_or(0, |e| e.timestamp),
+                self.events.last().map_or(0, |e| e.timestamp),
+            ),
+        }
+    }
+
+    fn player_id(&self, filtered: &FilteredEvents) -> usize {
+        self.players.iter().position(|p| p.id == filtered.player.id).unwrap()
+    }
+}
+
+// Players go by the names shown on logs.tf, keeping whatever they were called in the log as an alias.
+fn use_official_names(players: &mut [Player], meta: &LogMeta) {
+    for player in players {
+        if let Some(name) = meta.names.get(&player.id).filter(|name| **name != player.name) {
+            let old = std::mem::replace(&mut player.name, name.clone());
+            player.aliases.retain(|alias| alias != name);
+            if !player.aliases.contains(&old) {
+                player.aliases.push(old);
+            }
+        }
+    }
+}
+
+// --match, counting from 0.
+fn match_number(matches: &Matches) -> Result<Option<usize>, Error> {
+    match matches.opt_str("match") {
+        None => Ok(None),
+        Some(n) => n.parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .map(|n| Some(n - 1))
+            .ok_or_else(|| Error::usage("--match must be a match number, starting from 1.")),
+    }
+}
+
+fn logs_tf(matches: &Matches) -> LogsTf {
+    LogsTf::new(matches.opt_str("base-url"), matches.opt_present("offline"), matches.opt_present("meta"))
+}
+
+fn batching(matches: &Matches) -> Result<i64, Error> {
+    match matches.opt_str("batching") {
+        None => Ok(DEFAULT_BATCHING),
+        Some(val) => val.parse::<i64>()
+            .ok()
+            .filter(|b| *b > 0)
+            .ok_or_else(|| Error::usage("--batching must be a positive number of seconds.")),
+    }
+}
+
+fn team(name: &str) -> Result<Team, Error> {
+    match name {
+        "red" => Ok(Team::Red),
+        "blue" => Ok(Team::Blu),
+        _ => Err(Error::usage("--team must be one of red or blue.")),
+    }
+}
+
+// The players picked by --steamids, --steamid or --alias, in the order given.
+fn select_players<'a>(matches: &Matches, log: &'a ParsedLog) -> Result<Vec<&'a FilteredEvents>, Error> {
+    let find = |steamid: &str| {
+        log.filtered
+            .iter()
+            .find(|e| e.player.id == steamid)
+            .ok_or_else(|| not_found(steamid, &log.players))
+    };
+
+    if let Some(steamids) = matches.opt_str("steamids") {
+        steamids.split(',').map(|steamid| find(steamid.trim())).collect()
+    } else if let Some(steamid) = matches.opt_str("steamid") {
+        Ok(vec![find(&steamid)?])
+    } else if let Some(alias) = matches.opt_str("alias") {
+        // Partial matching for aliases is allowed.
+        log.filtered
+            .iter()
+            .find(|e| e.player.name.starts_with(&alias))
+            .map(|e| vec![e])
+            .ok_or_else(|| not_found(&alias, &log.players))
+    } else {
+        Err(Error::usage("One of either --steamid, --steamids or --alias is required."))
+    }
+}
+
+fn not_found(player: &str, players: &[Player]) -> Error {
+    let query = player.to_lowercase();
+    let mut closest: Vec<(usize, &Player)> = players
+        .iter()
+        .filter(|p| p.id != "Console")
+        .map(|p| {
+            let distance = std::iter::once(&p.name)
+                .chain(&p.aliases)
+                .chain(std::iter::once(&p.id))
+                .map(|name| edit_distance(&query, &name.to_lowercase()))
+                .min()
+                .unwrap();
+            (distance, p)
+        })
+        .collect();
+    closest.sort_by_key(|(distance, _)| *distance);
+
+    Error::PlayerNotFound {
+        player: player.to_owned(),
+        suggestions: closest.iter().take(3).map(|(_, p)| format!("{} {}", p.name, p.id)).collect(),
+    }
+}
+
+// Levenshtein distance, for suggesting players when there's a typo.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + (ca != *cb) as usize;
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+fn style(matches: &Matches) -> Result<Style, Error> {
+    let theme = match matches.opt_str("theme") {
+        None => Theme::dark(),
+        Some(name) => match Theme::named(&name) {
+            Some(theme) => theme,
+            None => Theme::from_file(&name).map_err(|e| Error::input("theme", e))?,
+        },
+    };
+
+    let mut canvas = Canvas::default();
+    // Whole pixels, and few enough of them that the canvas can actually be allocated.
+    let pixels = |name: &str| matches.opt_str(name).map(|val| val.parse::<u32>().ok().filter(|v| (100..=10000).contains(v)));
+    if let Some(width) = pixels("width") {
+        canvas.width = width.ok_or_else(|| Error::usage("--width must be a whole number of pixels from 100 to 10000."))? as f32;
+    }
+    if let Some(height) = pixels("height") {
+        canvas.height = height.ok_or_else(|| Error::usage("--height must be a whole number of pixels from 100 to 10000."))? as f32;
+    }
+    if let Some(scale) = matches.opt_str("scale").map(|val| val.parse::<f32>().ok().filter(|v| *v > 0.0)) {
+        canvas.scale = scale.ok_or_else(|| Error::usage("--scale must be a positive number."))?;
+    }
+    if canvas.graph_height() < 100.0 {
+        return Err(Error::usage("--height is too small for the --scale given."));
+    }
+
+    let font = load_font(matches.opt_str("font").as_deref()).map_err(|e| Error::input("font", e))?;
+    Ok(Style { theme, canvas, font, caption: None, chat: matches.opt_present("chat") })
+}
+
+// Where graphs get written: --output with its placeholders filled in, inside --output-dir.
+struct Output {
+    template: Option<String>,
+    dir: Option<String>,
+    force: bool,
+}
+
+// Placeholder values for a single output.
+struct OutputName<'a> {
+    name: &'a str,
+    steamid: &'a str,
+    steamid64: String,
+}
+
+impl<'a> OutputName<'a> {
+    fn player(player: &'a Player) -> Self {
+        Self {
+            name: &player.name,
+            steamid: &player.id,
+            steamid64: player.steamid64().map_or(String::new(), |id| id.to_string()),
+        }
+    }
+
+    // Team and overlay graphs aren't for a single player.
+    fn graph(name: &'a str) -> Self {
+        Self { name, steamid: name, steamid64: String::new() }
+    }
+}
+
+impl Output {
+    fn new(matches: &Matches) -> Self {
+        Self {
+            template: matches.opt_str("output"),
+            dir: matches.opt_str("output-dir"),
+            force: matches.opt_present("force"),
+        }
+    }
+
+    fn is_per_player(&self) -> bool {
+        self.template.as_ref().is_none_or(|t| t.contains("{name}") || t.contains("{steamid}") || t.contains("{steamid64}"))
+    }
+
+    // The path for `name`, using `default` if no --output was given. Fails rather than overwriting an existing file unless --force.
+    fn path(&self, default: &str, log: &ParsedLog, name: &OutputName, extension: &str) -> Result<PathBuf, Error> {
+        let template = self.template.as_deref().unwrap_or(default);
+        let file = template
+            .replace("{logid}", &sanitize(&log.id))
+            .replace("{name}", &sanitize(name.name))
+            .replace("{steamid}", &sanitize(&name.steamid.replace(':', ".")))
+            .replace("{steamid64}", &name.steamid64);
+
+        // Look at the template rather than the file, as names and steamids can have dots in them.
+        let file = match Path::new(template).extension() {
+            Some(_) => file,
+            None => format!("{}.{}", file, extension),
+        };
+        let path = match &self.dir {
+            Some(dir) => Path::new(dir).join(file),
+            None => PathBuf::from(file),
+        };
+
+        self.check(&path)?;
+        Ok(path)
+    }
+
+    fn check(&self, path: &Path) -> Result<(), Error> {
+        if path.exists() && !self.force {
+            return Err(Error::output(path.display().to_string(), "it already exists, use --force to overwrite it"));
+        }
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| Error::output(parent.display().to_string(), e))?;
+        }
+        Ok(())
+    }
+}
+
+// Player names can contain anything, so keep them from escaping the output directory. Leading dots go too, as `.` and
+// `..` would be the directories themselves and anything else starting with one would be hidden.
+fn sanitize(value: &str) -> String {
+    let sanitized: String = value.chars().map(|c| if c.is_alphanumeric() || "-_.[]".contains(c) { c } else { '_' }).collect();
+    let rest = sanitized.trim_start_matches('.');
+    "_".repeat(sanitized.len() - rest.len()) + rest
+}
+
+pub fn graph(matches: &Matches) -> Result<(), Error> {
+    let batching = batching(matches)?;
+    let format = match matches.opt_str("format").as_deref() {
+        None | Some("png") => OutputFormat::Png,
+        Some("html") => OutputFormat::Html,
+        Some(_) => return Err(Error::usage("--format must be one of png or html.")),
+    };
+    let team = matches.opt_str("team");
+    if let Some(team) = &team {
+        if let OutputFormat::Html = format {
+            return Err(Error::usage("--team only supports png output."));
+        }
+        if !matches!(team.as_str(), "red" | "blue" | "both") {
+            return Err(Error::usage("--team must be one of red, blue or both."));
+        }
+    }
+    let mut style = style(matches)?;
+    let output = Output::new(matches);
+
+    if let Some(steamid) = matches.opt_str("player-history") {
+        return player_history(matches, &steamid, batching, style, output);
+    }
+
+    let log = ParsedLog::from_matches(matches)?;
+    style.caption = log.caption();
+    let (players, events, rounds) = (&log.players, &log.events, &log.rounds);
+
+    // Team mode.
+    if let Some(team) = team {
+        let graph = output.path("out.png", &log, &OutputName::graph(&team), "png")?;
+        let (graph, highlights) = (graph.to_string_lossy(), graph.with_extension("txt"));
+        return match team.as_str() {
+            "both" => draw_momentum_graph(players, events, rounds, batching, &style, &graph),
+            team => {
+                output.check(&highlights)?;
+                draw_team_graph(self::team(team)?, players, events, rounds, batching, &style, &graph, &highlights.to_string_lossy())
+            }
+        }.map_err(|e| Error::output(graph, e));
+    }
+
+    let player_events = select_players(matches, &log)?;
+
+    // Overlay mode.
+    if matches.opt_present("overlay") {
+        if !matches.opt_present("steamids") {
+            return Err(Error::usage("--overlay requires --steamids."));
+        }
+        if let OutputFormat::Html = format {
+            return Err(Error::usage("--overlay only supports png output."));
+        }
+
+        let graph = output.path("out.png", &log, &OutputName::graph("overlay"), "png")?;
+        let graph = graph.to_string_lossy();
+        return draw_overlay(&player_events, players, events, rounds, batching, &style, &graph)
+            .map_err(|e| Error::output(graph, e));
+    }
+
+    let extension = match format {
+        OutputFormat::Png => "png",
+        OutputFormat::Html => "html",
+    };
+
+    // Batch mode writes one graph per player, into ./out unless told otherwise.
+    let batch = matches.opt_present("steamids");
+    let output = if batch && output.template.is_none() && output.dir.is_none() {
+        Output { dir: Some("out".to_owned()), ..output }
+    } else {
+        output
+    };
+    if batch && !output.is_per_player() {
+        return Err(Error::usage("--output needs one of {name}, {steamid} or {steamid64} when graphing several players."));
+    }
+    let default = if batch { format!("{{steamid}}.{}", extension) } else { format!("out.{}", extension) };
+
+    // Check every path up front, so nothing is written if any of them would overwrite something.
+    let mut paths = vec![];
+    for player_events in &player_events {
+        let path = output.path(&default, &log, &OutputName::player(&player_events.player), extension)?;
+        let highlights = path.with_extension("txt");
+        if let OutputFormat::Png = format {
+            output.check(&highlights)?;
+        }
+        paths.push((path, highlights));
+    }
+
+    for (player_events, (path, highlights)) in player_events.iter().zip(paths) {
+        let name = path.to_string_lossy();
+        match format {
+            OutputFormat::Png => draw_graph(player_events, players, events, rounds, batching, &style, &name, &highlights.to_string_lossy()),
+            OutputFormat::Html => write_report(player_events, &log.filtered, players, rounds, batching, &style, &name),
+        }.map_err(|e| Error::output(name, e))?;
+    }
+
+    Ok(())
+}
+
+// Graphs a player's most recent matches from logs.tf, oldest first, with a trend chart of how they did across all of them.
+fn player_history(matches: &Matches, steamid: &str, batching: i64, mut style: Style, output: Output) -> Result<(), Error> {
+    let last = match matches.opt_str("last") {
+        None => 5,
+        Some(last) => last.parse::<usize>().ok().filter(|l| *l > 0).ok_or_else(|| Error::usage("--last must be a positive number."))?,
+    };
+    let (steamid3, steamid64) = match (crate::steamid64(steamid), crate::steamid3(steamid)) {
+        (Some(steamid64), _) => (steamid.to_owned(), steamid64),
+        (None, Some(steamid3)) => (steamid3, steamid.parse::<u64>().unwrap()),
+        (None, None) => return Err(Error::usage("--player-history must be a SteamID3 or SteamID64.")),
+    };
+    if output.template.as_ref().is_some_and(|t| !t.contains("{logid}")) {
+        return Err(Error::usage("--output needs {logid} with --player-history, as there's a graph for each match."));
+    }
+
+    let output = Output { dir: Some(output.dir.unwrap_or_else(|| "history".to_owned())), ..output };
+    let dir = PathBuf::from(output.dir.as_deref().unwrap());
+    let (trend, trend_csv) = (dir.join("trend.png"), dir.join("trend.csv"));
+    output.check(&trend)?;
+    output.check(&trend_csv)?;
+
+    let logs_tf = logs_tf(matches);
+    let input = format!("the logs for {}", steamid3);
+    let logs = search_logs(&logs_tf, steamid64, last).map_err(|e| Error::input(&input, e))?;
+    if logs.is_empty() {
+        return Err(Error::input(input, "logs.tf doesn't have any"));
+    }
+
+    let mut name = steamid3.clone();
+    let mut points = vec![];
+    let mut csv = String::from("log,date,title,map,kills,assists,deaths,dpm,kad,highlights\n");
+    for (log_id, meta) in logs.into_iter().rev() {
+        // One bad log shouldn't stop the rest from being graphed.
+        let mut log = match ParsedLog::load(&LogSource::Id(log_id.clone()), &logs_tf, None) {
+            Ok(log) => log,
+            Err(e) => {
+                eprintln!("Skipping log {}: {}", log_id, e);
+                continue;
+            }
+        };
+        let Some(player_events) = log.filtered.iter().position(|f| f.player.id == steamid3) else {
+            eprintln!("Skipping log {}: {} isn't in it", log_id, steamid3);
+            continue;
+        };
+        // The search results already have the title, map and date.
+        log.meta.get_or_insert(meta);
+        let player_events = &log.filtered[player_events];
+        name = player_events.player.name.clone();
+
+        style.caption = log.caption();
+        let graph = output.path("{logid}/{steamid}.png", &log, &OutputName::player(&player_events.player), "png")?;
+        let highlights = graph.with_extension("txt");
+        output.check(&highlights)?;
+        let graph = graph.to_string_lossy();
+        draw_graph(player_events, &log.players, &log.events, &log.rounds, batching, &style, &graph, &highlights.to_string_lossy())
+            .map_err(|e| Error::output(graph, e))?;
+
+        let player_id = log.player_id(player_events);
+        let (from, to) = log.match_range();
+        let summary = PlayerSummary::new(player_events, player_id, from, to);
+        let assists = log.events
+            .iter()
+            .filter(|e| e.timestamp >= from && e.timestamp <= to)
+            .filter(|e| matches!(&e.event, EventType::Assist(assist) if assist.assister == player_id))
+            .count() as u32;
+        let kad = (summary.kills + assists) as f32 / summary.deaths.max(1) as f32;
+        let highlights = Timeline::build(&player_events.events, &[player_id], batching, &style.canvas).highlights.len();
+
+        let meta = log.meta.as_ref().unwrap();
+        let date = meta.date
+            .and_then(|d| chrono::DateTime::from_timestamp(d, 0))
+            .map_or(String::new(), |d| d.format("%Y-%m-%d").to_string());
+        csv += &format!(
+            "{},{},{},{},{},{},{},{:.0},{:.2},{}\n",
+            log_id, date, csv_field(&meta.title), csv_field(&meta.map), summary.kills, assists, summary.deaths, summary.dpm(), kad, highlights,
+        );
+        let label = if date.is_empty() { log_id } else { date };
+        points.push(TrendPoint { label, dpm: summary.dpm(), kad, highlights });
+    }
+
+    if points.is_empty() {
+        return Err(Error::input(input, "none of them could be graphed"));
+    }
+
+    style.caption = None;
+    let graph = trend.to_string_lossy();
+    draw_trend(&name, &points, &style, &graph).map_err(|e| Error::output(graph, e))?;
+    fs::write(&trend_csv, csv).map_err(|e| Error::output(trend_csv.display().to_string(), e))?;
+
+    Ok(())
+}
+
+pub fn summary(matches: &Matches) -> Result<(), Error> {
+    let round = match matches.opt_str("round") {
+        None => None,
+        Some(round) => Some(round.parse::<usize>().ok().filter(|r| *r > 0).ok_or_else(|| Error::usage("--round must be a round number, starting from 1."))?),
+    };
+
+    let log = ParsedLog::from_matches(matches)?;
+    let (from, to) = match round {
+        None => log.match_range(),
+        Some(round) => {
+            let round = log.rounds.get(round - 1).ok_or_else(|| Error::usage(format!("The log only has {} rounds.", log.rounds.len())))?;
+            (round.start, round.end)
+        }
+    };
+
+    if let Some(caption) = log.caption() {
+        println!("{}", caption);
+        if let Some(meta) = &log.meta {
+            // The caption is from logs.tf, so add what the server logged.
+            let info = log.info.describe();
+            if !info.is_empty() {
+                println!("{}", info);
+            }
+            if let Some(uploader) = &meta.uploader {
+                println!("uploaded by {}", uploader);
+            }
+        }
+    }
+    println!("{} {}", log.format.name(), log.mode.name());
+    println!();
+
+    // dpm is compared to what's usual for the format, where there is such a thing.
+    println!(
+        "{:<32} {:>5} {:>3} {:>3} {:>5} {:>6} {:>5} {:>7} {:>6} {:>6} {:>8} {:>9} {:>9} {:>11} {:>5}",
+        "player", "time", "k", "d", "k/d", "dmg", "dpm", "vs avg", "dt", "heal", "airshots", "headshots", "backstabs", "medic kills", "drops",
+    );
+    for s in summarize(&log.filtered, from, to) {
+        let vs_average = log.format.average_dpm().map_or("-".to_owned(), |average| format!("{:+.0}%", (s.dpm() / average - 1.0) * 100.0));
+        println!(
+            "{:<32} {:>5} {:>3} {:>3} {:>5.1} {:>6} {:>5.0} {:>7} {:>6} {:>6} {:>8} {:>9} {:>9} {:>11} {:>5}",
+            s.name, format_game_time(s.seconds), s.kills, s.deaths, s.kd(), s.damage, s.dpm(), vs_average, s.damage_taken, s.healing,
+            s.airshots, s.headshots, s.backstabs, s.medic_kills, s.drops,
+        );
+    }
+
+    Ok(())
+}
+
+pub fn highlights(matches: &Matches) -> Result<(), Error> {
+    let batching = batching(matches)?;
+    let log = ParsedLog::from_matches(matches)?;
+
+    let print = |name: &str, timeline: &Timeline| {
+        println!("{}:", name);
+        for (idx, highlight) in timeline.highlights.iter().enumerate() {
+            println!("{}: {} tick={}", idx, format_game_time(highlight.timestamp - timeline.match_start), highlight.tick);
+        }
+    };
+
+    if let Some(team) = matches.opt_str("team") {
+        let team = self::team(&team)?;
+        let roster = team_roster(&log.players, team);
+        let timeline = Timeline::build(&filter_events_for(&roster, &log.events)?, &roster, batching, &Canvas::default());
+        print(&format!("{:?}", team), &timeline);
+        return Ok(());
+    }
+
+    for player_events in select_players(matches, &log)? {
+        let timeline = Timeline::build(&player_events.events, &[log.player_id(player_events)], batching, &Canvas::default());
+        print(&player_events.player.name, &timeline);
+    }
+
+    Ok(())
+}
+
+// Quote fields that would otherwise break the row, e.g. player names with commas in them.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+// The batches that make up each player's graph, as CSV.
+pub fn export(matches: &Matches) -> Result<(), Error> {
+    let batching = batching(matches)?;
+    let format = matches.opt_str("format");
+    if !matches!(format.as_deref(), None | Some("csv") | Some("json") | Some("ndjson")) {
+        return Err(Error::usage("--format must be one of csv, json or ndjson."));
+    }
+    let log = ParsedLog::from_matches(matches)?;
+    match (format.as_deref(), matches.opt_present("chat")) {
+        (Some("json") | Some("ndjson"), true) => return Err(Error::usage("--chat is only exported as csv.")),
+        (Some("json"), false) => {
+            print!("{}", export_json(&log));
+            return Ok(());
+        }
+        (Some("ndjson"), false) => {
+            print!("{}", export_ndjson(&log));
+            return Ok(());
+        }
+        (_, true) => return export_chat(matches, &log),
+        _ => (),
+    }
+
+    println!("steamid,name,time,events,damage,damage_taken,healing,healing_received,kills,deaths,score");
+    for player_events in select_players(matches, &log)? {
+        let timeline = Timeline::build(&player_events.events, &[log.player_id(player_events)], batching, &Canvas::default());
+        for b in &timeline.batches {
+            println!(
+                "{},{},{},{},{},{},{},{},{},{},{}",
+                player_events.player.id, csv_field(&player_events.player.name), format_game_time(b.timestamp - timeline.match_start),
+                b.events, b.damage, b.damage_taken, b.healing, b.healing_received, b.kills, b.deaths, b.score,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// An overview of what was parsed out of the log.
+// Bumped whenever a field is removed or changes meaning, see SCHEMA.md.
+const EXPORT_SCHEMA: u32 = 1;
+
+// Events refer to players by their index in the log, which means nothing outside of it, so these are swapped for SteamIDs.
+const PLAYER_FIELDS: [&str; 6] = ["attacker", "victim", "healer", "target", "player", "assister"];
+
+// Everything about the log but its events: the schema version, the match and the players.
+fn export_header(log: &ParsedLog) -> serde_json::Value {
+    let (start, end) = log.match_range();
+    let players: Vec<_> = log.players.iter().map(|p| {
+        let mut player = serde_json::to_value(p).unwrap();
+        player["steamid64"] = json!(p.steamid64().map(|id| id.to_string()));
+        player
+    }).collect();
+
+    json!({
+        "schema_version": EXPORT_SCHEMA,
+        "log": {
+            "id": log.id,
+            "map": log.meta.as_ref().map(|meta| meta.map.clone()).filter(|map| !map.is_empty()).or_else(|| log.info.map.clone()),
+            "format": log.format.name(),
+            "mode": log.mode.name(),
+            "match": log.selected + 1,
+            "matches": log.matches.len(),
+            "start": start,
+            "end": end,
+        },
+        "players": players,
+    })
+}
+
+fn export_json(log: &ParsedLog) -> String {
+    let mut export = export_header(log);
+    export["events"] = log.events.iter().map(|e| export_event(e, log)).collect();
+    serde_json::to_string_pretty(&export).unwrap() + "\n"
+}
+
+// The header on the first line, then an event per line.
+fn export_ndjson(log: &ParsedLog) -> String {
+    let mut export = format!("{}\n", export_header(log));
+    for event in &log.events {
+        export += &format!("{}\n", export_event(event, log));
+    }
+    export
+}
+
+fn export_event(event: &Event, log: &ParsedLog) -> serde_json::Value {
+    let steamid = |value: &serde_json::Value| json!(value.as_u64().map(|idx| log.players[idx as usize].id.clone()));
+
+    let mut value = serde_json::to_value(event).unwrap();
+    value["time"] = json!(event.timestamp - log.match_range().0);
+    if let Some(data) = value.get_mut("data").and_then(|data| data.as_object_mut()) {
+        for (key, field) in data.iter_mut() {
+            if PLAYER_FIELDS.contains(&key.as_str()) {
+                *field = steamid(field);
+            } else if key == "cappers" {
+                *field = field.as_array().map_or(json!([]), |cappers| cappers.iter().map(steamid).collect());
+            }
+        }
+    }
+    value
+}
+
+// Every message in the match, with when it was said and who could see it.
+fn export_chat(matches: &Matches, log: &ParsedLog) -> Result<(), Error> {
+    let steamids: Vec<&str> = if ["steamid", "steamids", "alias"].iter().any(|name| matches.opt_present(name)) {
+        select_players(matches, log)?.iter().map(|f| f.player.id.as_str()).collect()
+    } else {
+        log.players.iter().map(|p| p.id.as_str()).collect()
+    };
+
+    print!("{}", chat_csv(log, &steamids));
+    Ok(())
+}
+
+// What `steamids` said during the match, at the game time they said it.
+fn chat_csv(log: &ParsedLog, steamids: &[&str]) -> String {
+    let (match_start, _) = log.match_range();
+    let mut csv = String::from("time,steamid,name,team,channel,message\n");
+    for event in &log.events {
+        let EventType::Say(say) = &event.event else { continue };
+        let player = &log.players[say.player];
+        if !steamids.contains(&player.id.as_str()) {
+            continue;
+        }
+
+        let channel = if say.team_only { "team" } else { "all" };
+        csv += &format!(
+            "{},{},{},{:?},{},{}\n",
+            format_game_time(event.timestamp - match_start), player.id, csv_field(&player.name), player.team, channel, csv_field(&say.text),
+        );
+    }
+    csv
+}
+
+pub fn inspect(matches: &Matches) -> Result<(), Error> {
+    let log = ParsedLog::from_matches(matches)?;
+    let (from, to) = log.match_range();
+
+    let mut counts = BTreeMap::new();
+    for event in &log.events {
+        *counts.entry(event.event.name()).or_insert(0) += 1;
+    }
+
+    println!("lines: {}", log.lines);
+    let info = &log.info;
+    if let Some(file) = &info.file {
+        println!("file: {}", file);
+    }
+    if let Some(version) = &info.version {
+        println!("version: {}", version);
+    }
+    if let Some(map) = &info.map {
+        println!("map: {}", map);
+    }
+    if !info.cvars.is_empty() {
+        println!("cvars: {}", info.cvars.len());
+        for (name, value) in &info.cvars {
+            println!("  {}: {}", name, value);
+        }
+    }
+    println!("players: {}", log.players.iter().filter(|p| matches!(p.team, Team::Red | Team::Blu)).count());
+    println!("matches: {}", log.matches.len());
+    let clock = |timestamp: i64| chrono::DateTime::from_timestamp(timestamp, 0).map_or(String::new(), |t| t.format("%H:%M:%S").to_string());
+    for (idx, m) in log.matches.iter().enumerate() {
+        let ended = match m.ended {
+            MatchEnd::GameOver => "game over",
+            MatchEnd::Restart => "restart",
+            MatchEnd::Gap => "gap",
+            MatchEnd::LogEnd => "end of log",
+        };
+        let selected = if idx == log.selected { " (selected)" } else { "" };
+        println!(
+            "  {}: {} - {} ({}), {} rounds, ended by {}{}",
+            idx + 1, clock(m.start), clock(m.end), format_game_time(m.end - m.start), m.rounds, ended, selected,
+        );
+    }
+
+    // Everything from here on is for the selected match.
+    println!("format: {}", log.format.name());
+    println!("mode: {}", log.mode.name());
+    println!("length: {}", format_game_time(to - from));
+    println!("events: {}", log.events.len());
+    for (name, count) in counts {
+        println!("  {}: {}", name, count);
+    }
+
+    println!("rounds: {}", log.rounds.len());
+    for (idx, round) in log.rounds.iter().enumerate() {
+        let winner = round.winner.map_or("none".to_owned(), |team| format!("{:?}", team));
+        let overtime = if round.overtime.is_some() { ", overtime" } else { "" };
+        println!(
+            "  {}: {} - {} ({}), winner: {}{}",
+            idx + 1, format_game_time(round.start - from), format_game_time(round.end - from),
+            format_game_time(round.end - round.start), winner, overtime,
+        );
+    }
+
+    Ok(())
+}
+
+// Everyone who played, with the classes they played in order and how long they were around for.
+pub fn players(matches: &Matches) -> Result<(), Error> {
+    let log = ParsedLog::from_matches(matches)?;
+
+    let mut seen: Vec<Option<(i64, i64)>> = vec![None; log.players.len()];
+    let mut classes: Vec<Vec<Class>> = vec![vec![]; log.players.len()];
+    for event in &log.events {
+        for player in event.players() {
+            let (first, _) = seen[player].unwrap_or((event.timestamp, event.timestamp));
+            seen[player] = Some((first, event.timestamp));
+        }
+
+        match &event.event {
+            EventType::Spawn(SpawnEvent { player, class }) | EventType::ChangeClass(ChangeClassEvent { player, class })
+                if *class != Class::Unknown && !classes[*player].contains(class) => {
+                classes[*player].push(*class);
+            }
+            _ => (),
+        }
+    }
+
+    // From when they were first seen until they were last seen, less any time they were disconnected.
+    let time_on_server = |idx: usize| seen[idx].map_or(0, |(first, last)| {
+        let away: i64 = log.filtered[idx].absences.iter().map(|(start, end)| (end.min(&last) - start.max(&first)).max(0)).sum();
+        last - first - away
+    });
+    let class_names = |classes: &[Class]| -> Vec<String> {
+        classes.iter().map(|c| format!("{:?}", c).to_lowercase()).collect()
+    };
+    let players = log.players.iter().enumerate().filter(|(_, p)| p.id != "Console");
+
+    if matches.opt_present("json") {
+        let players: Vec<_> = players.map(|(idx, p)| {
+            json!({
+                "name": p.name,
+                "aliases": p.aliases,
+                "steamid3": p.id,
+                "steamid64": p.steamid64().map(|id| id.to_string()),
+                "team": format!("{:?}", p.team),
+                "classes": class_names(&classes[idx]),
+                "seconds": time_on_server(idx),
+            })
+        }).collect();
+        println!("{}", serde_json::to_string_pretty(&players).unwrap());
+        return Ok(());
+    }
+
+    println!("{:<16} {:<17} {:<9} {:>5}  {:<24} name", "steamid3", "steamid64", "team", "time", "classes");
+    for (idx, p) in players {
+        let mut name = p.name.clone();
+        if !p.aliases.is_empty() {
+            name += &format!(" (also {})", p.aliases.join(", "));
+        }
+
+        println!(
+            "{:<16} {:<17} {:<9} {:>5}  {:<24} {}",
+            p.id,
+            p.steamid64().map_or(String::new(), |id| id.to_string()),
+            format!("{:?}", p.team),
+            format_game_time(time_on_server(idx)),
+            class_names(&classes[idx]).join(", "),
+            name,
+        );
+    }
+
+    Ok(())
+}
+
+// The logs given to `batch`: log ids, log files and directories of logs, from the command line and --list.
+fn batch_sources(matches: &Matches) -> Result<Vec<LogSource>, Error> {
+    let mut inputs: Vec<String> = matches.opt_strs("log-id").into_iter().chain(matches.opt_strs("log-file")).collect();
+    if let Some(list) = matches.opt_str("list") {
+        let contents = fs::read_to_string(&list).map_err(|e| Error::input(&list, e))?;
+        inputs.extend(
+            contents
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.to_owned()),
+        );
+    }
+    inputs.extend(matches.free.iter().cloned());
+
+    let mut sources = vec![];
+    for input in inputs {
+        let path = Path::new(&input);
+        if path.is_dir() {
+            let mut files: Vec<PathBuf> = fs::read_dir(path)
+                .map_err(|e| Error::input(&input, e))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ["log", "zip", "gz", "zst"].iter().any(|e| ext == *e)))
+                .collect();
+            files.sort();
+            sources.extend(files.into_iter().map(|f| LogSource::File(f.to_string_lossy().into_owned())));
+        } else if !path.exists() && !input.is_empty() && input.chars().all(|c| c.is_ascii_digit()) {
+            sources.push(LogSource::Id(input));
+        } else {
+            sources.push(LogSource::File(input));
+        }
+    }
+
+    if sources.is_empty() {
+        return Err(Error::usage("No logs given, pass log ids, log files or directories, or use --list."));
+    }
+    Ok(sources)
+}
+
+// What `batch` got out of a single log, for the index.
+struct BatchEntry {
+    dir: String,
+    lines: usize,
+    length: i64,
+    rounds: usize,
+    red_wins: usize,
+    blu_wins: usize,
+    players: usize,
+    outputs: Vec<PathBuf>,
+}
+
+// The players given by --steamids, for commands that graph them in whichever logs they're in.
+fn steamids(matches: &Matches) -> Vec<String> {
+    matches
+        .opt_str("steamids")
+        .map_or(vec![], |steamids| steamids.split(',').map(|s| s.trim().to_owned()).collect())
+}
+
+// Parses one log and writes its summary and graphs to `dir`.
+#[allow(clippy::too_many_arguments)]
+fn batch_log(source: &LogSource, logs_tf: &LogsTf, selected: Option<usize>, dir: &Path, steamids: &[String], batching: i64, style: &mut Style, force: bool) -> Result<BatchEntry, Error> {
+    let log = ParsedLog::load(source, logs_tf, selected)?;
+    let output = Output { template: None, dir: Some(dir.to_string_lossy().into_owned()), force };
+    let outputs = write_outputs(&log, &output, steamids, batching, style)?;
+    let (from, to) = log.match_range();
+
+    let wins = |team: Team| log.rounds.iter().filter(|r| r.winner == Some(team)).count();
+    Ok(BatchEntry {
+        dir: dir.to_string_lossy().into_owned(),
+        lines: log.lines,
+        length: to - from,
+        rounds: log.rounds.len(),
+        red_wins: wins(Team::Red),
+        blu_wins: wins(Team::Blu),
+        players: log.players.iter().filter(|p| matches!(p.team, Team::Red | Team::Blu)).count(),
+        outputs,
+    })
+}
+
+// A summary.csv of everyone's stats, and a graph for each of `steamids` that's in the log. Used by batch, listen and watch.
+fn write_outputs(log: &ParsedLog, output: &Output, steamids: &[String], batching: i64, style: &mut Style) -> Result<Vec<PathBuf>, Error> {
+    style.caption = log.caption();
+    let (from, to) = log.match_range();
+
+    let summary = output.path("summary.csv", log, &OutputName::graph("summary"), "csv")?;
+    let mut csv = String::from("steamid,name,kills,deaths,damage,dpm,damage_taken,healing,airshots,headshots,backstabs,medic_kills,drops,seconds\n");
+    for s in summarize(&log.filtered, from, to) {
+        csv += &format!(
+            "{},{},{},{},{},{:.0},{},{},{},{},{},{},{},{}\n",
+            s.steamid, csv_field(&s.name), s.kills, s.deaths, s.damage, s.dpm(), s.damage_taken, s.healing,
+            s.airshots, s.headshots, s.backstabs, s.medic_kills, s.drops, s.seconds,
+        );
+    }
+    fs::write(&summary, csv).map_err(|e| Error::output(summary.display().to_string(), e))?;
+    let mut outputs = vec![summary];
+
+    // Only the players that were in this log get a graph.
+    for player_events in log.filtered.iter().filter(|f| steamids.contains(&f.player.id)) {
+        let graph = output.path("{steamid}.png", log, &OutputName::player(&player_events.player), "png")?;
+        let highlights = graph.with_extension("txt");
+        output.check(&highlights)?;
+
+        let name = graph.to_string_lossy();
+        draw_graph(player_events, &log.players, &log.events, &log.rounds, batching, style, &name, &highlights.to_string_lossy())
+            .map_err(|e| Error::output(name, e))?;
+        outputs.push(graph);
+        outputs.push(highlights);
+    }
+
+    Ok(outputs)
+}
+
+// A bad log shouldn't take the rest of the batch down with it, so panics are reported like any other failure.
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => format!("panicked: {}", msg),
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(msg) => format!("panicked: {}", msg),
+            Err(_) => "panicked".to_owned(),
+        },
+    }
+}
+
+// Processes many logs in parallel, writing each log's outputs to its own directory along with an index of
+// every log and a report of the ones that failed.
+pub fn batch(matches: &Matches) -> Result<(), Error> {
+    let batching = batching(matches)?;
+    let jobs = match matches.opt_str("jobs") {
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+        Some(jobs) => jobs.parse::<usize>().ok().filter(|j| *j > 0).ok_or_else(|| Error::usage("--jobs must be a positive number."))?,
+    };
+    let steamids = steamids(matches);
+    let force = matches.opt_present("force");
+    let dir = PathBuf::from(matches.opt_str("output-dir").unwrap_or_else(|| "batch".to_owned()));
+    let sources = batch_sources(matches)?;
+    let logs_tf = logs_tf(matches);
+    let selected = match_number(matches)?;
+
+    // Fonts can't be shared between threads, so this just catches a bad theme or font before starting.
+    style(matches)?;
+
+    let output = Output { template: None, dir: None, force };
+    let (index, failures) = (dir.join("index.csv"), dir.join("failures.txt"));
+    output.check(&index)?;
+    output.check(&failures)?;
+
+    // Logs from different directories can have the same name.
+    let mut dirs: Vec<PathBuf> = vec![];
+    for source in &sources {
+        let name = sanitize(&source.id());
+        let mut log_dir = dir.join(&name);
+        let mut n = 1;
+        while dirs.contains(&log_dir) {
+            n += 1;
+            log_dir = dir.join(format!("{}-{}", name, n));
+        }
+        dirs.push(log_dir);
+    }
+
+    let before = Instant::now();
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<BatchEntry, String>>>> = Mutex::new((0..sources.len()).map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(sources.len()) {
+            scope.spawn(|| {
+                let mut style = style(matches);
+                loop {
+                    let idx = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(source) = sources.get(idx) else { break };
+
+                    let result = match &mut style {
+                        Ok(style) => panic::catch_unwind(AssertUnwindSafe(|| batch_log(source, &logs_tf, selected, &dirs[idx], &steamids, batching, style, force)))
+                            .unwrap_or_else(|payload| Err(Error::input(source.name(), panic_message(payload))))
+                            .map_err(|e| e.to_string()),
+                        Err(e) => Err(e.to_string()),
+                    };
+
+                    let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+                    match &result {
+                        Ok(_) => eprintln!("[{}/{}] {}", done, sources.len(), source.name()),
+                        Err(e) => eprintln!("[{}/{}] {}", done, sources.len(), e),
+                    }
+                    results.lock().unwrap()[idx] = Some(result);
+                }
+            });
+        }
+    });
+    let results: Vec<Result<BatchEntry, String>> = results.into_inner().unwrap().into_iter().map(|r| r.unwrap()).collect();
+
+    let mut csv = String::from("log,source,status,dir,lines,length,rounds,red_wins,blu_wins,players,outputs,error\n");
+    let mut report = String::new();
+    for (source, result) in sources.iter().zip(&results) {
+        let location = match source {
+            LogSource::Id(_) => "logs.tf",
+            LogSource::File(file) => file,
+        };
+        let row = match result {
+            Ok(e) => format!(
+                "ok,{},{},{},{},{},{},{},{},",
+                csv_field(&e.dir), e.lines, format_game_time(e.length), e.rounds, e.red_wins, e.blu_wins, e.players,
+                csv_field(&e.outputs.iter().map(|o| o.to_string_lossy()).collect::<Vec<_>>().join(" ")),
+            ),
+            Err(e) => {
+                report += &format!("{}\n", e);
+                format!("failed,,,,,,,,,{}", csv_field(e))
+            }
+        };
+        csv += &format!("{},{},{}\n", csv_field(&source.id()), csv_field(location), row);
+    }
+
+    fs::write(&index, csv).map_err(|e| Error::output(index.display().to_string(), e))?;
+    fs::write(&failures, &report).map_err(|e| Error::output(failures.display().to_string(), e))?;
+
+    let failed = results.iter().filter(|r| r.is_err()).count();
+    eprintln!("Processed {} logs in {:.2?}, {} failed", sources.len(), before.elapsed(), failed);
+    if failed > 0 {
+        return Err(Error::input(format!("{} of {} logs", failed, sources.len()), format!("see {}", failures.display())));
+    }
+
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+// Lists what's in the download cache, or prunes it.
+pub fn cache(matches: &Matches) -> Result<(), Error> {
+    let number = |name: &str| -> Result<Option<f64>, Error> {
+        match matches.opt_str(name) {
+            None => Ok(None),
+            Some(val) => val.parse::<f64>().ok().filter(|v| *v >= 0.0).map(Some)
+                .ok_or_else(|| Error::usage(format!("--{} must be a positive number.", name))),
+        }
+    };
+    let older_than = number("older-than")?;
+    let max_size = number("max-size")?;
+    if (older_than.is_some() || max_size.is_some()) && !matches.opt_present("prune") {
+        return Err(Error::usage("--older-than and --max-size need --prune."));
+    }
+
+    let dir = cache_dir().ok_or_else(|| Error::input("cache", "neither $XDG_CACHE_HOME nor $HOME are set"))?;
+    let entries = cache_entries(&dir).map_err(|e| Error::input(dir.display().to_string(), e))?;
+    let total: u64 = entries.iter().map(|e| e.size).sum();
+
+    if !matches.opt_present("prune") {
+        println!("cache: {}", dir.display());
+        println!("files: {} ({})", entries.len(), format_size(total));
+        for entry in &entries {
+            let modified = chrono::DateTime::<chrono::Local>::from(entry.modified);
+            println!(
+                "  {:<24} {:>10}  {}",
+                entry.path.strip_prefix(&dir).unwrap_or(&entry.path).display(), format_size(entry.size), modified.format("%Y-%m-%d %H:%M"),
+            );
+        }
+        return Ok(());
+    }
+
+    // Oldest first, so --max-size keeps the most recent downloads.
+    let now = SystemTime::now();
+    let mut remaining = total;
+    let (mut removed, mut freed) = (0, 0);
+    for entry in &entries {
+        let age = now.duration_since(entry.modified).map_or(0.0, |age| age.as_secs_f64() / (24.0 * 60.0 * 60.0));
+        let prune = match (older_than, max_size) {
+            (None, None) => true,
+            (older_than, max_size) => {
+                older_than.is_some_and(|days| age > days)
+                    || max_size.is_some_and(|mb| remaining as f64 > mb * 1024.0 * 1024.0)
+            }
+        };
+        if !prune {
+            continue;
+        }
+
+        fs::remove_file(&entry.path).map_err(|e| Error::output(entry.path.display().to_string(), e))?;
+        remaining -= entry.size;
+        removed += 1;
+        freed += entry.size;
+    }
+
+    println!("Removed {} files ({}), {} left", removed, format_size(freed), format_size(remaining));
+    Ok(())
+}
+
+// Receives a log from a server as it's played (`logaddress_add`), rewriting the summary and graphs at the end of every round.
+pub fn listen(matches: &Matches) -> Result<(), Error> {
+    let batching = batching(matches)?;
+    let mut style = style(matches)?;
+    let steamids = steamids(matches);
+    let secret = matches.opt_str("secret");
+    let output = Output { template: None, dir: Some(matches.opt_str("output-dir").unwrap_or_else(|| "live".to_owned())), force: true };
+
+    let bind = matches.opt_str("bind").unwrap_or_else(|| "0.0.0.0:27500".to_owned());
+    let socket = UdpSocket::bind(&bind).map_err(|e| Error::input(&bind, e))?;
+    eprintln!("Listening on {}", bind);
+
+    // Drawing takes a while, so packets are received on their own thread to keep them from being dropped meanwhile.
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        let mut packet = [0; 65536];
+        loop {
+            let line = socket.recv_from(&mut packet).map(|(len, _)| parse_packet(&packet[..len], secret.as_deref()));
+            let failed = line.is_err();
+            if sender.send(line).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let mut live = LiveLog::default();
+    for line in lines {
+        let Some(line) = line.map_err(|e| Error::input(&bind, e))? else { continue };
+
+        let game_over = match live.push_line(&line) {
+            Some(EventType::RoundWin(_)) => false,
+            Some(EventType::GameOver) => true,
+            _ => continue,
+        };
+
+        // The match carries on regardless, so failing to write the outputs isn't fatal.
+        let log = ParsedLog::new("live".to_owned(), &bind, live.lines, live.players.clone(), live.matches.clone(), live.info.clone(), None, None);
+        match log.and_then(|log| write_outputs(&log, &output, &steamids, batching, &mut style)) {
+            Ok(outputs) => eprintln!("Wrote {} outputs to {}", outputs.len(), output.dir.as_deref().unwrap()),
+            Err(e) => eprintln!("Error: {}", e),
+        }
+
+        if game_over {
+            if matches.opt_present("once") {
+                return Ok(());
+            }
+            live.take();
+        }
+    }
+
+    Ok(())
+}
+
+// Follows the newest log in a server's logs directory as it's written, writing the summary and graphs for each match when it ends.
+pub fn watch(matches: &Matches) -> Result<(), Error> {
+    let batching = batching(matches)?;
+    let mut style = style(matches)?;
+    let steamids = steamids(matches);
+    let out = PathBuf::from(matches.opt_str("output-dir").unwrap_or_else(|| "matches".to_owned()));
+    let dir = match matches.free.as_slice() {
+        [dir] => dir.clone(),
+        _ => return Err(Error::usage("watch needs the server's logs directory, e.g. `log-grapher watch tf/logs`.")),
+    };
+    if !Path::new(&dir).is_dir() {
+        return Err(Error::input(dir, "it isn't a directory"));
+    }
+
+    let mut follower = LogFollower::new(&dir);
+    let mut live = LiveLog::default();
+    // Matches are named after the log they're in, and numbered if there's more than one.
+    let (mut name, mut finished) = (String::new(), 0);
+    loop {
+        let line = match follower.next().map_err(|e| Error::input(&dir, e))? {
+            Tail::Line(line) => line,
+            Tail::Rotated(path) => {
+                eprintln!("Following {}", path.display());
+                name = path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned());
+                finished = 0;
+                live = LiveLog::default();
+                continue;
+            }
+            Tail::Idle => {
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+        };
+        if !matches!(live.push_line(&line), Some(EventType::GameOver)) {
+            continue;
+        }
+
+        finished += 1;
+        let id = if finished == 1 { name.clone() } else { format!("{}-{}", name, finished) };
+        let output = Output { template: None, dir: Some(out.join(&id).to_string_lossy().into_owned()), force: matches.opt_present("force") };
+
+        // Keep watching for the next match even if this one couldn't be written.
+        let live = live.take();
+        let log = ParsedLog::new(id, &name, live.lines, live.players, live.matches, live.info, None, None);
+        match log.and_then(|log| write_outputs(&log, &output, &steamids, batching, &mut style)) {
+            Ok(outputs) => eprintln!("Wrote {} outputs to {}", outputs.len(), output.dir.as_deref().unwrap()),
+            Err(e) => eprintln!("Error: {}", e),
+        }
+
+        if matches.opt_present("once") {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn small_log() -> ParsedLog {
+        ParsedLog::load(&LogSource::File(fixture("small.log")), &LogsTf::new(None, true, false), None).unwrap()
+    }
+
+    #[test]
+    fn sanitized_names() {
+        assert_eq!(sanitize("[VIP] Zach"), "[VIP]_Zach");
+        assert_eq!(sanitize("a/b\\c"), "a_b_c");
+        assert_eq!(sanitize("."), "_");
+        assert_eq!(sanitize(".."), "__");
+        assert_eq!(sanitize(".hidden"), "_hidden");
+        assert_eq!(sanitize("../../etc"), "___.._etc");
+        assert_eq!(sanitize("a..b."), "a..b.");
+        assert_eq!(sanitize(""), "");
+    }
+
+    #[test]
+    fn output_stays_in_dir() {
+        let dir = std::env::temp_dir().join(format!("log-grapher-output-{}", std::process::id()));
+        let output = Output { template: Some("{name}/graph.png".to_owned()), dir: Some(dir.to_string_lossy().into_owned()), force: false };
+        let log = small_log();
+
+        for name in ["..", ".", "../..", "/"] {
+            let path = output.path("out.png", &log, &OutputName::graph(name), "png").unwrap();
+            assert_eq!(path.parent().unwrap().parent().unwrap(), dir, "{}", name);
+            assert!(path.components().all(|c| matches!(c, std::path::Component::Normal(_) | std::path::Component::RootDir)), "{}", name);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn graph_team_checked_first() {
+        let mut opts = Options::new();
+        input_options(&mut opts);
+        graph_options(&mut opts);
+        let matches = opts.parse(["--log-file", &fixture("missing.log"), "--team", "rde"]).unwrap();
+        let e = graph(&matches).unwrap_err();
+        assert_eq!(e.exit_code(), 2, "{}", e);
+    }
+
+    #[test]
+    fn canvas_size() {
+        let mut opts = Options::new();
+        style_options(&mut opts);
+        let style = |args: &[&str]| style(&opts.parse(args).unwrap()).map(|s| (s.canvas.width, s.canvas.height));
+
+        assert_eq!(style(&["--width", "1920", "--height", "1080"]).unwrap(), (1920.0, 1080.0));
+        assert_eq!(style(&["--width", "100", "--height", "10000"]).unwrap(), (100.0, 10000.0));
+        for bad in ["0", "0.5", "99", "10001", "1e9", "-800", "wide"] {
+            assert_eq!(style(&["--width", bad]).unwrap_err().exit_code(), 2, "{}", bad);
+            assert_eq!(style(&["--height", bad]).unwrap_err().exit_code(), 2, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("", "tal"), 3);
+        assert_eq!(edit_distance("tal", ""), 3);
+        assert_eq!(edit_distance("toqoz", "toqoz"), 0);
+        assert_eq!(edit_distance("toqoz", "toqz"), 1);
+        assert_eq!(edit_distance("toqoz", "tokoz"), 1);
+        // A swap counts as two substitutions.
+        assert_eq!(edit_distance("tal", "tla"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        // Characters, not bytes.
+        assert_eq!(edit_distance("zoë", "zoe"), 1);
+        assert_eq!(edit_distance("ブラウ", "ブルー"), 2);
+        assert_eq!(edit_distance("🐢", ""), 1);
+    }
+
+    #[test]
+    fn suggestions() {
+        let player = |name: &str, id: &str, aliases: &[&str]| Player {
+            name: name.to_owned(),
+            id: id.to_owned(),
+            team: Team::Red,
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        };
+        let players = vec![
+            player("Console", "Console", &[]),
+            player("Sentar", "[U:1:166427044]", &[]),
+            player("b4nny", "[U:1:10403365]", &["FROYO b4nny"]),
+            player("Tal", "[U:1:91618645]", &[]),
+            player("Toqoz", "[U:1:83248160]", &[]),
+            player("Thyme", "[U:1:126485428]", &[]),
+        ];
+
+        let suggested = |query: &str| match not_found(query, &players) {
+            Error::PlayerNotFound { suggestions, .. } => suggestions,
+            e => panic!("{}", e),
+        };
+        // Closest first, ignoring case, with ties kept in log order.
+        assert_eq!(suggested("TOQ"), ["Tal [U:1:91618645]", "Toqoz [U:1:83248160]", "Thyme [U:1:126485428]"]);
+        assert_eq!(suggested("toqos"), ["Toqoz [U:1:83248160]", "Tal [U:1:91618645]", "Thyme [U:1:126485428]"]);
+        // Aliases and steamids count too, and the console is never suggested.
+        assert_eq!(suggested("froyo b4nn")[0], "b4nny [U:1:10403365]");
+        assert_eq!(suggested("[U:1:91618646]")[0], "Tal [U:1:91618645]");
+        assert!(!suggested("consol").iter().any(|s| s.starts_with("Console")));
+        assert!(matches!(not_found("tal", &[]), Error::PlayerNotFound { suggestions, .. } if suggestions.is_empty()));
+    }
+
+    // The exported output is what other tools are written against, so any change to it should be deliberate.
+    #[test]
+    fn export_golden() {
+        let log = small_log();
+        assert_eq!(export_json(&log), fs::read_to_string(fixture("small.json")).unwrap());
+        assert_eq!(export_ndjson(&log), fs::read_to_string(fixture("small.ndjson")).unwrap());
+    }
+
+    #[test]
+    fn export_chat_golden() {
+        let log = ParsedLog::load(&LogSource::File(fixture("chat.log")), &LogsTf::new(None, true, false), None).unwrap();
+        assert_eq!(chat_csv(&log, &["[U:1:91618645]", "[U:1:152978378]"]), fs::read_to_string(fixture("chat.csv")).unwrap());
+
+        // Only the players asked for, and only during the match.
+        let tal = chat_csv(&log, &["[U:1:91618645]"]);
+        assert_eq!(tal.lines().skip(1).collect::<Vec<_>>(), ["00:29,[U:1:91618645],tal,Red,team,\"sorry, he airshot me\""]);
+    }
+
+    #[test]
+    fn export_fields() {
+        let log = small_log();
+        let export: serde_json::Value = serde_json::from_str(&export_json(&log)).unwrap();
+        assert_eq!(export["schema_version"], json!(EXPORT_SCHEMA));
+        assert_eq!(export["players"][0]["steamid"], json!("[U:1:91618645]"));
+        assert_eq!(export["players"][0].get("id"), None);
+
+        // Events are tagged with their type, with what's specific to each under data.
+        let events = export["events"].as_array().unwrap();
+        assert_eq!(events.len(), log.events.len());
+        assert_eq!(events[0], json!({ "type": "round_start", "timestamp": 1629375016, "time": 0 }));
+        let kill = events.iter().find(|e| e["type"] == "kill").unwrap();
+        assert_eq!(kill["data"]["attacker"], json!("[U:1:152978378]"));
+        assert_eq!(kill["data"].get("event"), None);
+        let win = events.iter().find(|e| e["type"] == "round_win").unwrap();
+        assert_eq!(win["data"], json!("Blu"));
+
+        // Every line of ndjson stands on its own, with the header first.
+        let lines: Vec<serde_json::Value> = export_ndjson(&log).lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines[0]["schema_version"], json!(EXPORT_SCHEMA));
+        assert_eq!(lines[0].get("events"), None);
+        assert_eq!(&lines[1..], events.as_slice());
+    }
+}
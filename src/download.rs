@@ -1,37 +1,492 @@
-use std::{fs::File, io::{BufReader, Cursor, Read}};
-use zip::read::ZipArchive;
-use std::io::BufRead;
+use std::{fs::{self, File}, io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom}};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use flate2::read::{DeflateDecoder, GzDecoder};
+use zip::{read::ZipArchive, CompressionMethod};
 
-use reqwest;
+pub const DEFAULT_BASE_URL: &str = "https://logs.tf";
 
-pub fn log_from_download(log_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-	let target = format!("https://logs.tf/logs/log_{}.log.zip", log_id);
-	dbg!(&target);
-	let bytes = reqwest::blocking::get(target)?.bytes()?;
+// Where logs are downloaded from, and the cache they're kept in so each log is only downloaded once.
+pub struct LogsTf {
+	base_url: String,
+	cache: Option<PathBuf>,
+	offline: bool,
+	// Whether to fetch each log's LogMeta as well.
+	pub meta: bool,
+}
 
-	let reader = Cursor::new(bytes);
-	let mut archive = ZipArchive::new(reader)?;
-	let mut log_file = archive.by_name(&format!("log_{}.log", log_id))?;
+impl LogsTf {
+	pub fn new(base_url: Option<String>, offline: bool, meta: bool) -> Self {
+		let base_url = base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_owned());
+		Self { base_url: base_url.trim_end_matches('/').to_owned(), cache: cache_dir(), offline, meta }
+	}
+
+	// `path` on logs.tf, always downloaded.
+	fn get(&self, path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+		if self.offline {
+			return Err("it can't be downloaded with --offline".into());
+		}
+		Ok(reqwest::blocking::get(format!("{}{}", self.base_url, path))?.error_for_status()?.bytes()?.to_vec())
+	}
 
-	let mut log_string = String::new();
-	log_file.read_to_string(&mut log_string)?;
+	// `path` on logs.tf, from the cache at `cached` if it's there.
+	fn fetch(&self, path: &str, cached: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+		let cached = self.cache.as_ref().map(|dir| dir.join(cached));
+		if let Some(bytes) = cached.as_ref().and_then(|c| fs::read(c).ok()) {
+			return Ok(bytes);
+		}
+		if self.offline {
+			return Err("it isn't in the cache and --offline was given".into());
+		}
+
+		let bytes = self.get(path)?;
+		if let Some(cached) = cached {
+			// A failed write only means it gets downloaded again next time.
+			let _ = write_atomically(&cached, &bytes);
+		}
+		Ok(bytes)
+	}
+}
+
+// Written to a temporary file first so another process never reads half a download.
+fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+	fs::write(&tmp, bytes)?;
+	fs::rename(&tmp, path)
+}
+
+// $XDG_CACHE_HOME/log-grapher, or ~/.cache/log-grapher.
+pub fn cache_dir() -> Option<PathBuf> {
+	let base = std::env::var_os("XDG_CACHE_HOME")
+		.map(PathBuf::from)
+		.filter(|p| p.is_absolute())
+		.or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+	Some(base.join("log-grapher"))
+}
 
-	// TODO: is there a more efficient way.
-	let lines = log_string
-					.lines()
-					.map(|l| l.to_owned())
-					.collect();
+pub struct CacheEntry {
+	pub path: PathBuf,
+	pub size: u64,
+	pub modified: SystemTime,
+}
+
+// Everything in the cache, oldest first.
+pub fn cache_entries(dir: &Path) -> io::Result<Vec<CacheEntry>> {
+	let mut entries = vec![];
+	let mut dirs = vec![dir.to_owned()];
+	while let Some(dir) = dirs.pop() {
+		let read_dir = match fs::read_dir(&dir) {
+			Ok(read_dir) => read_dir,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+			Err(e) => return Err(e),
+		};
+		for entry in read_dir {
+			let entry = entry?;
+			let metadata = entry.metadata()?;
+			if metadata.is_dir() {
+				dirs.push(entry.path());
+			} else {
+				entries.push(CacheEntry { path: entry.path(), size: metadata.len(), modified: metadata.modified()? });
+			}
+		}
+	}
+
+	entries.sort_by_key(|e| e.modified);
+	Ok(entries)
+}
+
+// What logs.tf knows about a log that the log itself doesn't.
+#[derive(Debug, Clone, Default)]
+pub struct LogMeta {
+	pub title: String,
+	pub map: String,
+	// Unix time it was uploaded.
+	pub date: Option<i64>,
+	pub uploader: Option<String>,
+	// Player names as shown on logs.tf, by SteamID3.
+	pub names: HashMap<String, String>,
+}
+
+impl LogMeta {
+	// e.g. "RGL S12 W1: BLU vs RED, cp_process_f12, 2023-04-02".
+	pub fn describe(&self) -> String {
+		let mut parts: Vec<String> = vec![self.title.clone(), self.map.clone()];
+		if let Some(date) = self.date.and_then(|d| chrono::DateTime::from_timestamp(d, 0)) {
+			parts.push(date.format("%Y-%m-%d").to_string());
+		}
+		parts.retain(|p| !p.is_empty());
+		parts.join(", ")
+	}
+}
 
-	Ok(lines)
+// The id ends up in the cache's file names.
+fn check_log_id(log_id: &str) -> Result<(), Box<dyn Error>> {
+	if log_id.is_empty() || !log_id.chars().all(|c| c.is_ascii_digit()) {
+		return Err("log ids are numbers".into());
+	}
+	Ok(())
 }
 
-pub fn log_from_file(file: &str) -> Result<Vec<String>, ()> {
-    let log = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), file);
-    let file = File::open(log).expect("Failed to open log file.");
-    let reader = BufReader::new(file);
-    let lines: Vec<String> = reader.lines()
-                                   .map(|l| l.unwrap_or("".to_owned()))
-                                   .collect();
+pub fn meta_from_download(logs_tf: &LogsTf, log_id: &str) -> Result<LogMeta, Box<dyn Error>> {
+	check_log_id(log_id)?;
+	let bytes = logs_tf.fetch(&format!("/api/v1/log/{}", log_id), &format!("meta/{}.json", log_id))?;
+	let json: serde_json::Value = serde_json::from_slice(&bytes)?;
 
-	Ok(lines)
-}
\ No newline at end of file
+	let info = &json["info"];
+	let names = json["names"]
+		.as_object()
+		.map(|names| names.iter().filter_map(|(id, name)| Some((id.clone(), name.as_str()?.to_owned()))).collect())
+		.unwrap_or_default();
+	Ok(LogMeta {
+		title: info["title"].as_str().unwrap_or_default().to_owned(),
+		map: info["map"].as_str().unwrap_or_default().to_owned(),
+		date: info["date"].as_i64(),
+		uploader: info["uploader"]["name"].as_str().map(|name| name.to_owned()),
+		names,
+	})
+}
+
+// The most recent logs a player is in, newest first. Not cached, as there are new ones after every match.
+pub fn search_logs(logs_tf: &LogsTf, steamid64: u64, limit: usize) -> Result<Vec<(String, LogMeta)>, Box<dyn Error>> {
+	let bytes = logs_tf.get(&format!("/api/v1/log?player={}&limit={}", steamid64, limit))?;
+	let json: serde_json::Value = serde_json::from_slice(&bytes)?;
+
+	let logs = json["logs"].as_array().ok_or("unexpected response from the search api")?;
+	Ok(logs
+		.iter()
+		.filter_map(|log| {
+			let meta = LogMeta {
+				title: log["title"].as_str().unwrap_or_default().to_owned(),
+				map: log["map"].as_str().unwrap_or_default().to_owned(),
+				date: log["date"].as_i64(),
+				..Default::default()
+			};
+			Some((log["id"].as_u64()?.to_string(), meta))
+		})
+		.collect())
+}
+
+pub fn log_from_download(logs_tf: &LogsTf, log_id: &str) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+	check_log_id(log_id)?;
+	let bytes = logs_tf.fetch(&format!("/logs/log_{}.log.zip", log_id), &format!("logs/{}.zip", log_id))?;
+	log_from_zip(Cursor::new(bytes), Some(&format!("log_{}.log", log_id)))
+}
+
+// A log on disk, or stdin for "-". Zipped (as downloaded from logs.tf), gzipped and zstd compressed logs are
+// decompressed as they're read, going by their magic numbers rather than the extension so it works for stdin too.
+pub fn log_from_file(file: &str) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+	let is_zip = |magic: &[u8]| magic.starts_with(b"PK\x03\x04");
+	if file == "-" {
+		let mut reader = BufReader::new(io::stdin());
+		if is_zip(reader.fill_buf()?) {
+			// A zip's index is at the end, so a zipped log on stdin has to be read in full to find it.
+			let mut bytes = vec![];
+			reader.read_to_end(&mut bytes)?;
+			return log_from_zip(Cursor::new(bytes), None);
+		}
+		return decompress(Box::new(reader));
+	}
+
+	let mut reader = BufReader::new(File::open(file)?);
+	if is_zip(reader.fill_buf()?) {
+		return log_from_zip(reader, None);
+	}
+	decompress(Box::new(reader))
+}
+
+fn decompress(mut reader: Box<dyn BufRead>) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+	let magic = reader.fill_buf()?;
+	if magic.starts_with(&[0x1f, 0x8b]) {
+		Ok(Box::new(BufReader::new(GzDecoder::new(reader))))
+	} else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+		Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)))
+	} else {
+		Ok(reader)
+	}
+}
+
+// The log called `name` in the archive, or the first .log in it if no name is given. Entries borrow the archive
+// they're in, so rather than unzip the whole entry up front, this finds where its data starts and inflates it
+// from the archive's reader as it's read.
+fn log_from_zip<R: Read + Seek + 'static>(reader: R, name: Option<&str>) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+	let mut archive = ZipArchive::new(reader)?;
+	let name = match name {
+		Some(name) => name.to_owned(),
+		None => archive
+			.file_names()
+			.find(|name| name.ends_with(".log"))
+			.ok_or("no .log file in the archive")?
+			.to_owned(),
+	};
+
+	let entry = archive.by_name(&name)?;
+	let (compression, start, size) = (entry.compression(), entry.data_start(), entry.compressed_size());
+	drop(entry);
+
+	let mut reader = archive.into_inner();
+	reader.seek(SeekFrom::Start(start))?;
+	let data = reader.take(size);
+	match compression {
+		CompressionMethod::Stored => Ok(Box::new(BufReader::new(data))),
+		CompressionMethod::Deflated => Ok(Box::new(BufReader::new(DeflateDecoder::new(data)))),
+		method => Err(format!("unsupported compression in the archive: {}", method).into()),
+	}
+}
+
+#[cfg(test)]
+pub mod tests {
+	use std::io::Write;
+	use std::net::TcpListener;
+	use std::sync::{Arc, Mutex};
+	use zip::write::{FileOptions, ZipWriter};
+
+	use super::*;
+	use crate::commands::{LogSource, ParsedLog};
+
+	// A stand-in for logs.tf, answering each path in `routes` with its status and body, and 404 for anything else.
+	// Returns the base url and the paths requested so far.
+	pub fn serve(routes: Vec<(String, u16, Vec<u8>)>) -> (String, Arc<Mutex<Vec<String>>>) {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let base_url = format!("http://{}", listener.local_addr().unwrap());
+		let requested = Arc::new(Mutex::new(vec![]));
+		let seen = requested.clone();
+		std::thread::spawn(move || {
+			for stream in listener.incoming() {
+				let mut stream = stream.unwrap();
+				let mut request = String::new();
+				for line in BufReader::new(&stream).lines() {
+					let line = line.unwrap();
+					if line.is_empty() {
+						break;
+					}
+					request.push_str(&line);
+					request.push('\n');
+				}
+				let path = request.split(' ').nth(1).unwrap_or_default().to_owned();
+				let (status, body) = routes
+					.iter()
+					.find(|(p, _, _)| *p == path)
+					.map_or((404, &b"not found"[..]), |(_, status, body)| (*status, &body[..]));
+				seen.lock().unwrap().push(path);
+				let head = format!("HTTP/1.1 {} -\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+				let _ = stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(body));
+			}
+		});
+		(base_url, requested)
+	}
+
+	// An empty directory to use as the cache, so the real one is never touched.
+	pub fn temp_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("log-grapher-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	pub fn logs_tf(base_url: &str, cache: &Path, offline: bool) -> LogsTf {
+		LogsTf { base_url: base_url.to_owned(), cache: Some(cache.to_owned()), offline, meta: false }
+	}
+
+	pub fn zipped(name: &str, log: &[u8]) -> Vec<u8> {
+		let mut zip = ZipWriter::new(Cursor::new(vec![]));
+		zip.start_file(name, FileOptions::default()).unwrap();
+		zip.write_all(log).unwrap();
+		zip.finish().unwrap().into_inner()
+	}
+
+	const LOG: &[u8] = b"L 08/19/2021 - 12:10:16: World triggered \"Round_Start\"\n";
+
+	fn read(log: Box<dyn BufRead>) -> Vec<String> {
+		log.lines().map(|l| l.unwrap()).collect()
+	}
+
+	#[test]
+	fn download() {
+		let (base_url, requested) = serve(vec![("/logs/log_42.log.zip".to_owned(), 200, zipped("log_42.log", LOG))]);
+		let cache = temp_dir("download");
+
+		let log = log_from_download(&logs_tf(&base_url, &cache, false), "42").unwrap();
+		assert_eq!(read(log), ["L 08/19/2021 - 12:10:16: World triggered \"Round_Start\""]);
+		assert_eq!(*requested.lock().unwrap(), ["/logs/log_42.log.zip"]);
+
+		// Kept under its id, and not downloaded again.
+		assert!(cache.join("logs/42.zip").exists());
+		log_from_download(&logs_tf(&base_url, &cache, false), "42").unwrap();
+		assert_eq!(requested.lock().unwrap().len(), 1);
+		fs::remove_dir_all(&cache).unwrap();
+	}
+
+	#[test]
+	fn not_found() {
+		let (base_url, _) = serve(vec![]);
+		let cache = temp_dir("not-found");
+		let logs_tf = logs_tf(&base_url, &cache, false);
+
+		let e = log_from_download(&logs_tf, "42").err().unwrap();
+		assert!(e.to_string().contains("404"), "{}", e);
+		let e = ParsedLog::load(&LogSource::Id("42".to_owned()), &logs_tf, None).err().unwrap();
+		assert_eq!(e.exit_code(), 3);
+		// Nothing is cached, so it's tried again next time.
+		assert!(!cache.join("logs/42.zip").exists());
+		fs::remove_dir_all(&cache).unwrap();
+	}
+
+	#[test]
+	fn bad_zip() {
+		let (base_url, _) = serve(vec![
+			("/logs/log_1.log.zip".to_owned(), 200, b"<html>not a zip</html>".to_vec()),
+			("/logs/log_2.log.zip".to_owned(), 200, zipped("log_3.log", LOG)),
+		]);
+		let cache = temp_dir("bad-zip");
+		let logs_tf = logs_tf(&base_url, &cache, false);
+
+		assert!(log_from_download(&logs_tf, "1").is_err());
+		let e = ParsedLog::load(&LogSource::Id("1".to_owned()), &logs_tf, None).err().unwrap();
+		assert_eq!(e.exit_code(), 3);
+		// An archive without the log it should have.
+		assert!(log_from_download(&logs_tf, "2").is_err());
+		fs::remove_dir_all(&cache).unwrap();
+	}
+
+	#[test]
+	fn offline() {
+		let (base_url, requested) = serve(vec![("/logs/log_7.log.zip".to_owned(), 200, zipped("log_7.log", LOG))]);
+		let cache = temp_dir("offline");
+		fs::create_dir_all(cache.join("logs")).unwrap();
+		fs::write(cache.join("logs/42.zip"), zipped("log_42.log", LOG)).unwrap();
+
+		let logs_tf = logs_tf(&base_url, &cache, true);
+		assert_eq!(read(log_from_download(&logs_tf, "42").unwrap()).len(), 1);
+		let e = log_from_download(&logs_tf, "7").err().unwrap();
+		assert!(e.to_string().contains("--offline"), "{}", e);
+		assert!(requested.lock().unwrap().is_empty());
+		fs::remove_dir_all(&cache).unwrap();
+	}
+
+	#[test]
+	fn meta() {
+		let json = br#"{
+			"info": {"title": "RGL S12 W1: BLU vs RED", "map": "cp_process_f12", "date": 1680393600, "uploader": {"id": "76561198043513888", "name": "oh no"}},
+			"names": {"[U:1:83248160]": "oh no", "[U:1:91618645]": "tal"}
+		}"#;
+		let (base_url, requested) = serve(vec![("/api/v1/log/42".to_owned(), 200, json.to_vec())]);
+		let cache = temp_dir("meta");
+		let logs_tf = logs_tf(&base_url, &cache, false);
+
+		let meta = meta_from_download(&logs_tf, "42").unwrap();
+		assert_eq!(meta.describe(), "RGL S12 W1: BLU vs RED, cp_process_f12, 2023-04-02");
+		assert_eq!(meta.uploader.as_deref(), Some("oh no"));
+		assert_eq!(meta.names.get("[U:1:91618645]").map(String::as_str), Some("tal"));
+
+		// The second time it comes from the cache.
+		assert!(cache.join("meta/42.json").exists());
+		meta_from_download(&logs_tf, "42").unwrap();
+		assert_eq!(requested.lock().unwrap().len(), 1);
+		fs::remove_dir_all(&cache).unwrap();
+	}
+
+	#[test]
+	fn meta_missing_fields() {
+		let (base_url, _) = serve(vec![("/api/v1/log/42".to_owned(), 200, br#"{"info": {"map": "koth_product_final"}}"#.to_vec())]);
+		let cache = temp_dir("meta-missing");
+
+		let meta = meta_from_download(&logs_tf(&base_url, &cache, false), "42").unwrap();
+		assert_eq!(meta.describe(), "koth_product_final");
+		assert_eq!(meta.uploader, None);
+		assert!(meta.names.is_empty());
+		fs::remove_dir_all(&cache).unwrap();
+	}
+
+	#[test]
+	fn search() {
+		let json = br#"{
+			"success": true, "results": 3, "total": 120, "parameters": {"player": "76561198043513888", "limit": 3},
+			"logs": [
+				{"id": 3003, "title": "newest", "map": "cp_gullywash_f9", "date": 1680393600, "views": 10, "players": 12},
+				{"title": "no id", "map": "cp_snakewater_final1", "date": 1680300000},
+				{"id": 3001, "title": "oldest", "map": "koth_product_final"}
+			]
+		}"#;
+		let path = "/api/v1/log?player=76561198043513888&limit=3".to_owned();
+		let (base_url, requested) = serve(vec![(path.clone(), 200, json.to_vec())]);
+		let cache = temp_dir("search");
+		let logs_tf = logs_tf(&base_url, &cache, false);
+
+		let logs = search_logs(&logs_tf, 76561198043513888, 3).unwrap();
+		let ids: Vec<&str> = logs.iter().map(|(id, _)| id.as_str()).collect();
+		assert_eq!(ids, ["3003", "3001"]);
+		assert_eq!(logs[0].1.describe(), "newest, cp_gullywash_f9, 2023-04-02");
+		assert_eq!(logs[1].1.date, None);
+
+		// Searches aren't cached.
+		search_logs(&logs_tf, 76561198043513888, 3).unwrap();
+		assert_eq!(*requested.lock().unwrap(), [path.clone(), path]);
+		assert!(fs::read_dir(&cache).unwrap().next().is_none());
+		fs::remove_dir_all(&cache).unwrap();
+	}
+
+	#[test]
+	fn search_errors() {
+		let (base_url, _) = serve(vec![("/api/v1/log?player=76561198043513888&limit=5".to_owned(), 200, br#"{"success": false}"#.to_vec())]);
+		let cache = temp_dir("search-errors");
+
+		assert!(search_logs(&logs_tf(&base_url, &cache, false), 76561198043513888, 5).is_err());
+		// Any other limit 404s.
+		assert!(search_logs(&logs_tf(&base_url, &cache, false), 76561198043513888, 6).is_err());
+		assert!(search_logs(&logs_tf(&base_url, &cache, true), 76561198043513888, 5).is_err());
+		fs::remove_dir_all(&cache).unwrap();
+	}
+
+	// However a log is compressed, it's read the same as the plain file.
+	#[test]
+	fn compressed() {
+		let plain = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/small.log")).unwrap();
+		let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+		gz.write_all(&plain).unwrap();
+		// The first .log in the archive, whatever else is in there.
+		let mut zip = ZipWriter::new(Cursor::new(vec![]));
+		zip.start_file("readme.txt", FileOptions::default()).unwrap();
+		zip.write_all(b"not the log").unwrap();
+		zip.start_file("L0819011.log", FileOptions::default()).unwrap();
+		zip.write_all(&plain).unwrap();
+
+		let dir = temp_dir("compressed");
+		let load = |name: &str| {
+			let path = dir.join(name).to_string_lossy().into_owned();
+			let lines = read(log_from_file(&path).unwrap());
+			let log = ParsedLog::load(&LogSource::File(path), &LogsTf::new(None, true, false), None).unwrap();
+			(lines, format!("{:?}", log.events), format!("{:?}", log.players))
+		};
+		fs::write(dir.join("small.log"), &plain).unwrap();
+		let expected = load("small.log");
+		assert_eq!(expected.0.len(), 15);
+
+		for (name, bytes) in [
+			("small.log.gz", gz.finish().unwrap()),
+			("small.log.zst", zstd::encode_all(&plain[..], 0).unwrap()),
+			("small.zip", zip.finish().unwrap().into_inner()),
+		] {
+			fs::write(dir.join(name), &bytes).unwrap();
+			assert_eq!(load(name), expected, "{}", name);
+			// Going by what's in the file, not its name.
+			fs::write(dir.join("renamed.log"), &bytes).unwrap();
+			assert_eq!(load("renamed.log"), expected, "{}", name);
+		}
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn log_ids() {
+		let cache = temp_dir("log-ids");
+		let logs_tf = logs_tf("http://127.0.0.1:9", &cache, true);
+		for log_id in ["", "../42", "42.zip"] {
+			assert!(log_from_download(&logs_tf, log_id).is_err(), "{}", log_id);
+		}
+		fs::remove_dir_all(&cache).unwrap();
+	}
+}
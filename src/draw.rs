@@ -1,20 +1,23 @@
-use std::fs::File;
-use std::io::BufWriter;
-use std::io::Write;
+use std::fs;
+use std::io;
+use std::sync::{Arc, Once};
 
 use raqote::*;
 use font_kit::family_name::FamilyName;
+use font_kit::font::Font;
 use font_kit::properties::Properties;
 use font_kit::source::SystemSource;
 
 use crate::events::*;
-use crate::Player;
+use crate::theme::Theme;
+use crate::{Player, Team};
 
-const REAL_WIDTH: f32 = 1280.0;
+// Layout, in pixels at a scale of 1.
 const KEY_SPACE: f32 = 80.0;
-const REAL_HEIGHT: f32 = 720.0;
-const HEIGHT: f32 = REAL_HEIGHT - KEY_SPACE - 10.0;
+const AXIS_SPACE: f32 = 20.0;
 const LINE_PADDING: f32 = 10.0;
+const FONT_SIZE: f32 = 14.0;
+const AXIS_FONT_SIZE: f32 = 12.0;
 
 // Same as default, but no anti-aliasing.
 const DRAW_OPTIONS: DrawOptions = DrawOptions {
@@ -47,33 +50,110 @@ const STROKE_STYLE_BASELINE: StrokeStyle = StrokeStyle {
     dash_offset: 0.,
 };
 
-/*
-Courtney scheme.
-const BG_COLOR: SolidSource = SolidSource { r: 21, g: 25, b: 39, a: 255 };
-const FG_COLOR: SolidSource = SolidSource { r: 69, g: 75, b: 94, a: 255 };
-const DAMAGE_COLOR: SolidSource = SolidSource { r: 126, g: 134, b: 160, a: 255 };
-const KILL_COLOR: SolidSource = SolidSource { r: 184, g: 189, b: 204, a: 255 };
-const HEADSHOT_BACKSTAB_REFLECT_COLOR: SolidSource = SolidSource { r: 235, g: 253, b: 175, a: 255 };
-const AIRSHOT_COLOR: SolidSource = SolidSource { r: 184, g: 239, b: 6, a: 255 };
-const SHOT_HIT_COLOR: SolidSource = SolidSource { r: 156, g: 149, b: 220, a: 255 };
-const MEDIC_KILL_COLOR: SolidSource = SolidSource { r: 255, g: 180, b: 153, a: 255 };
-const MEDIC_DROP_COLOR: SolidSource = SolidSource { r: 255, g: 31, b: 87, a: 255 };
-const HEAL_COLOR: SolidSource = SolidSource { r: 112, g: 188, b: 109, a: 255 };
-const DEATH_COLOR: SolidSource = SolidSource { r: 183, g: 22, b: 51, a: 255 };
-*/
-
-//const BG_COLOR: SolidSource = SolidSource { r: 41, g: 40, b: 40, a: 255 };
-const BG_COLOR: SolidSource = SolidSource { r: 24, g: 26, b: 32, a: 255 };
-const FG_COLOR: SolidSource = SolidSource { r: 212, g: 190, b: 152, a: 255 };
-const DAMAGE_COLOR: SolidSource = SolidSource { r: 90, g: 82, b: 76, a: 255 };
-const KILL_COLOR: SolidSource = SolidSource { r: 169, g: 182, b: 101, a: 255 };
-const HEADSHOT_BACKSTAB_REFLECT_COLOR: SolidSource = SolidSource { r: 216, g: 166, b: 87, a: 255 };
-const AIRSHOT_COLOR: SolidSource = SolidSource { r: 125, g: 174, b: 163, a: 255 };
-const SHOT_HIT_COLOR: SolidSource = SolidSource { r: 105, g: 98, b: 92, a: 255 };
-const MEDIC_KILL_COLOR: SolidSource = SolidSource { r: 211, g: 134, b: 155, a: 255 };
-const MEDIC_DROP_COLOR: SolidSource = SolidSource { r: 180, g: 65, b: 96, a: 255 };
-const HEAL_COLOR: SolidSource = SolidSource { r: 137, g: 180, b: 130, a: 255 };
-const DEATH_COLOR: SolidSource = SolidSource { r: 234, g: 105, b: 98, a: 255 };
+const STROKE_STYLE_OVERLAY: StrokeStyle = StrokeStyle {
+    width: 2.,
+    cap: LineCap::Round,
+    join: LineJoin::Round,
+    miter_limit: 10.,
+    dash_array: Vec::new(),
+    dash_offset: 0.,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Canvas {
+    pub width: f32,
+    pub height: f32,
+    // Multiplier for everything drawn at a fixed size, e.g. 2.0 for high-DPI output.
+    pub scale: f32,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self {
+            width: 1280.0,
+            height: 720.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Canvas {
+    // Height of the graph itself, above the time axis and key.
+    pub fn graph_height(&self) -> f32 {
+        self.height - (KEY_SPACE + AXIS_SPACE + 10.0) * self.scale
+    }
+
+    pub fn line_start(&self) -> f32 {
+        LINE_PADDING * self.scale
+    }
+
+    pub fn line_end(&self) -> f32 {
+        self.width - LINE_PADDING * self.scale
+    }
+}
+
+pub struct Style {
+    pub theme: Theme,
+    pub canvas: Canvas,
+    pub font: Font,
+    // Which log the graph is of, drawn above the title.
+    pub caption: Option<String>,
+    // Mark chat messages along the top of the graph.
+    pub chat: bool,
+}
+
+impl Style {
+    fn stroke(&self, base: StrokeStyle) -> StrokeStyle {
+        StrokeStyle { width: base.width * self.canvas.scale, ..base }
+    }
+
+    fn text(&self, dt: &mut DrawTarget, size: f32, text: &str, x: f32, y: f32) {
+        // raqote panics on characters the font doesn't have, e.g. emoji in player names.
+        let text: String = text.chars().map(|c| if self.font.glyph_for_char(c).is_some() { c } else { '?' }).collect();
+        dt.draw_text(&self.font, size * self.canvas.scale, &text, Point::new(x, y), &Source::Solid(self.theme.fg), &DRAW_OPTIONS_TEXT);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LineKind {
+    Damage,
+    Heal,
+    HeadshotBackstabReflect,
+    Airshot,
+    Kill,
+    ShotHit,
+    MedicKill,
+    MedicDrop,
+    Death,
+}
+
+impl LineKind {
+    pub fn color(self, theme: &Theme) -> SolidSource {
+        match self {
+            LineKind::Damage => theme.damage,
+            LineKind::Heal => theme.heal,
+            LineKind::HeadshotBackstabReflect => theme.headshot_backstab_reflect,
+            LineKind::Airshot => theme.airshot,
+            LineKind::Kill => theme.kill,
+            LineKind::ShotHit => theme.shot_hit,
+            LineKind::MedicKill => theme.medic_kill,
+            LineKind::MedicDrop => theme.medic_drop,
+            LineKind::Death => theme.death,
+        }
+    }
+}
+
+// Lines and labels for the key, from bottom to top.
+pub const KEY: [(LineKind, &str); 8] = [
+    (LineKind::Damage, "damage"),
+    (LineKind::Heal, "healing"),
+    (LineKind::HeadshotBackstabReflect, "headshot/backstab/reflect"),
+    (LineKind::Airshot, "airshot"),
+    (LineKind::Kill, "kill"),
+    (LineKind::MedicKill, "medic_kill"),
+    (LineKind::MedicDrop, "medic_drop"),
+    (LineKind::Death, "death"),
+];
 
 const DAMAGE_MULTIPLIER: f32 = 1.0;
 const HEAL_MULTIPLIER: f32 = 1.0;
@@ -87,29 +167,31 @@ const HIT_VALUE: f32 = 20.0;
 //const AIRSHOT_DISTANCE_MULTIPLIER: f32 = 10.0;
 const MEDIC_KILL_VALUE: f32 = 100.0;
 const MEDIC_DROP_VALUE: f32 = 200.0;
+// A single player's batch with more score than this is a highlight.
+const HIGHLIGHT_SCORE: f32 = 250.0;
 
-struct EventLine {
-    x: f32,
-    from_y: f32,
-    to_y: f32,
-    cap: bool,
-    color: SolidSource,
+pub struct EventLine {
+    pub x: f32,
+    pub from_y: f32,
+    pub to_y: f32,
+    pub cap: bool,
+    pub kind: LineKind,
 }
 
 impl EventLine {
-    fn new(x: f32, from_y: f32, to_y: f32, cap: bool, color: SolidSource) -> Self {
+    fn new(x: f32, from_y: f32, to_y: f32, cap: bool, kind: LineKind) -> Self {
         Self {
-            x, from_y, to_y, cap, color,
+            x, from_y, to_y, cap, kind,
         }
     }
 }
 
-struct EventLines {
-    lines: Vec<EventLine>,
+pub struct EventLines {
+    pub lines: Vec<EventLine>,
     current_positive: f32,
     current_negative: f32,
-    global_y_scale: f32,
-    max_height: f32,
+    pub global_y_scale: f32,
+    pub max_height: f32,
 }
 
 impl EventLines {
@@ -128,7 +210,7 @@ impl EventLines {
         self.current_negative = 0.0;
     }
 
-    fn add_positive(&mut self, x: f32, amount: f32, cap: bool, color: SolidSource) {
+    fn add_positive(&mut self, x: f32, amount: f32, cap: bool, kind: LineKind) {
         let start = self.current_positive;
         self.current_positive += amount;
         if self.current_positive > self.max_height * 0.5 {
@@ -138,10 +220,10 @@ impl EventLines {
             }
         }
 
-        self.lines.push(EventLine::new(x, start, self.current_positive, cap, color));
+        self.lines.push(EventLine::new(x, start, self.current_positive, cap, kind));
     }
 
-    fn add_negative(&mut self, x: f32, amount: f32, cap: bool, color: SolidSource) {
+    fn add_negative(&mut self, x: f32, amount: f32, cap: bool, kind: LineKind) {
         let start = self.current_negative;
         self.current_negative -= amount;
         if self.current_negative.abs() > self.max_height * 0.5 {
@@ -151,191 +233,751 @@ impl EventLines {
             }
         }
 
-        self.lines.push(EventLine::new(x, start, self.current_negative, cap, color));
+        self.lines.push(EventLine::new(x, start, self.current_negative, cap, kind));
     }
 
-    fn draw(&self, mut dt: &mut DrawTarget) {
+    fn draw(&self, dt: &mut DrawTarget, style: &Style) {
         let base_y = self.max_height * 0.5;
 
         for line in &self.lines {
             let from = line.from_y * self.global_y_scale;
             let to = line.to_y * self.global_y_scale;
+            let color = line.kind.color(&style.theme);
 
-            draw_line(&mut dt, line.x, base_y - from, line.x, base_y - to, line.color);
+            draw_line(dt, style, line.x, base_y - from, line.x, base_y - to, color);
             if line.cap {
-                draw_cap(&mut dt, line.x, base_y - to, 3.0, line.color);
+                draw_cap(dt, style, line.x, base_y - to, 3.0 * style.canvas.scale, color);
             }
         }
     }
 }
 
-pub fn draw_graph(filtered: &FilteredEvents, players: &Vec<Player>, batching: i64, graph_filename: &str, highlights_filename: &str) {
-    println!("Making timeline for player: {}, batching: {}", filtered.player.name, batching);
-    let player_id = players.iter().position(|i| i.id == filtered.player.id).unwrap();
+// What happened to the player within a single batching period.
+#[derive(Debug, Clone, Default)]
+pub struct Batch {
+    pub x: f32,
+    pub timestamp: i64,
+    pub events: usize,
+    pub damage: u32,
+    pub damage_taken: u32,
+    pub healing: u32,
+    pub healing_received: u32,
+    pub kills: u32,
+    pub deaths: u32,
+    pub score: f32,
+    pub positive: f32,
+    pub negative: f32,
+}
 
-    let font = SystemSource::new()
-        .select_best_match(&[FamilyName::Monospace], &Properties::new()).unwrap()
-        .load().unwrap();
+#[derive(Debug, Clone)]
+pub struct Highlight {
+    pub x: f32,
+    pub timestamp: i64,
+    pub tick: f32,
+}
 
-    let mut dt = DrawTarget::new(REAL_WIDTH as i32, REAL_HEIGHT as i32);
-    dt.clear(BG_COLOR);
+pub struct Timeline {
+    pub start: i64,
+    pub match_start: i64,
+    pub end: i64,
+    pub canvas: Canvas,
+    pub lines: EventLines,
+    pub batches: Vec<Batch>,
+    pub highlights: Vec<Highlight>,
+}
 
-    // Demo starts recording 5 seconsd before game start.
-    let start = &filtered.events.first().unwrap().timestamp - 5;
-    let end = &filtered.events.last().unwrap().timestamp;
-    let duration = (end - start) as f32;
+impl Timeline {
+    // `player_ids` are the players whose events count as positive, e.g. a single player or a team's roster.
+    pub fn build(events: &[Event], player_ids: &[usize], batching: i64, canvas: &Canvas) -> Self {
+        let involved = |id: &usize| player_ids.contains(id);
+        // Teams rack up score much faster than a single player, so need more to stand out.
+        let highlight_score = HIGHLIGHT_SCORE * (player_ids.len().max(1) as f32).sqrt();
+        // Demo starts recording 5 seconsd before game start.
+        let match_start = events.first().unwrap().timestamp;
+        let start = match_start - 5;
+        let end = events.last().unwrap().timestamp;
 
-    let (line_start, line_end) = (LINE_PADDING, REAL_WIDTH - LINE_PADDING);
+        let mut timeline = Self {
+            start,
+            match_start,
+            end,
+            canvas: *canvas,
+            lines: EventLines::new(canvas.graph_height()),
+            batches: vec![],
+            highlights: vec![],
+        };
 
-    let mut lines = EventLines::new(HEIGHT);
-    let mut noteworthy = vec![];
+        let mut iter = events.iter().peekable();
+        while let Some(event) = iter.next() {
+            let mut buffer = vec![event.clone()];
+            // Consume all events within the combine period.
+            while let Some(next) = iter.next_if(|next| next.timestamp - event.timestamp < batching) {
+                buffer.push(next.clone());
+            }
 
-    let mut iter = filtered.events.iter().peekable();
-    while let Some(event) = iter.next() {
-        let mut buffer = vec![event.clone()];
-        // Consume all events within the combine period.
-        while let Some(next) = iter.next_if(|next| next.timestamp - event.timestamp < batching) {
-            buffer.push(next.clone());
-        }
+            let mid_timestamp = buffer[(buffer.len()-1) / 2].timestamp;
+            let x = timeline.x_at(mid_timestamp);
 
-        let mid_timestamp = buffer[(buffer.len()-1) / 2].timestamp;
-        let progress = (mid_timestamp - start) as f32 / duration;
-        let x = lerp(line_start, line_end, progress);
+            let mut batch = Batch { x, timestamp: mid_timestamp, events: buffer.len(), ..Default::default() };
+            let lines = &mut timeline.lines;
+            lines.reset();
 
-        lines.reset();
+            for ev in buffer {
+                match ev.event {
+                    EventType::Damage(damage) => {
+                        let dmg = damage.damage as f32 * DAMAGE_MULTIPLIER;
+                        if involved(&damage.attacker) {
+                            lines.add_positive(x, dmg, false, LineKind::Damage);
+                            batch.damage += damage.damage;
+                            batch.score += dmg;
 
-        let mut score = 0.0;
-        for ev in buffer {
-            match ev.event {
-                EventType::Damage(damage) => {
-                    let dmg = damage.damage as f32 * DAMAGE_MULTIPLIER;
-                    if damage.attacker == player_id {
-                        lines.add_positive(x, dmg, false, DAMAGE_COLOR);
-                        score += dmg;
+                            if damage.headshot {
+                                lines.add_positive(x, HEADSHOT_VALUE, true, LineKind::HeadshotBackstabReflect);
+                                batch.score += HEADSHOT_VALUE;
+                            }
 
-                        if damage.headshot {
-                            lines.add_positive(x, HEADSHOT_VALUE, true, HEADSHOT_BACKSTAB_REFLECT_COLOR);
-                            score += HEADSHOT_VALUE;
+                            if damage.airshot {
+                                lines.add_positive(x, AIRSHOT_VALUE, true, LineKind::Airshot);
+                                batch.score += AIRSHOT_VALUE;
+                            }
+                        } else if involved(&damage.victim) {
+                            lines.add_negative(x, dmg, false, LineKind::Damage);
+                            batch.damage_taken += damage.damage;
+                        }
+                    },
+                    EventType::Heal(heal) => {
+                        let healing = heal.healing as f32 * HEAL_MULTIPLIER;
+                        if involved(&heal.healer) {
+                            lines.add_positive(x, healing, false, LineKind::Heal);
+                            batch.healing += heal.healing;
+                        } else if involved(&heal.target) {
+                            lines.add_negative(x, healing, false, LineKind::Heal);
+                            batch.healing_received += heal.healing;
                         }
+                    }
+                    EventType::Kill(kill) => {
+                        if involved(&kill.attacker) {
+                            // We don't care about headshot kills because it is already captured by the damage.
+                            if kill.weapon.starts_with("deflect") || kill.backstab {
+                                lines.add_positive(x, HEADSHOT_BACKSTAB_REFLECT_KILL_VALUE, true, LineKind::HeadshotBackstabReflect);
+                                batch.score += HEADSHOT_BACKSTAB_REFLECT_KILL_VALUE;
+                            }
 
-                        if damage.airshot {
-                            lines.add_positive(x, AIRSHOT_VALUE, true, AIRSHOT_COLOR);
-                            score += AIRSHOT_VALUE;
+                            lines.add_positive(x, KILL_VALUE, true, LineKind::Kill);
+                            batch.kills += 1;
+                            batch.score += KILL_VALUE;
+                        } else if involved(&kill.victim) {
+                            lines.add_negative(x, DEATH_VALUE, true, LineKind::Death);
+                            batch.deaths += 1;
                         }
-                    } else if damage.victim == player_id {
-                        lines.add_negative(x, dmg, false, DAMAGE_COLOR);
+                    },
+                    EventType::Hit(hit) if involved(&hit.player) => {
+                        lines.add_positive(x, HIT_VALUE, false, LineKind::ShotHit);
+                        batch.score += HIT_VALUE;
                     }
-                },
-                EventType::Heal(heal) => {
-                    let healing = heal.healing as f32 * HEAL_MULTIPLIER;
-                    if heal.healer == player_id {
-                        lines.add_positive(x, healing, false, HEAL_COLOR);
-                    } else if heal.target == player_id {
-                        lines.add_negative(x, healing, false, HEAL_COLOR)
+                    EventType::MedicDeath(md) => {
+                        if involved(&md.attacker) {
+                            if md.drop {
+                                lines.add_positive(x, MEDIC_DROP_VALUE, true, LineKind::MedicDrop);
+                            } else {
+                                lines.add_positive(x, MEDIC_KILL_VALUE, true, LineKind::MedicKill);
+                            }
+                        } else if involved(&md.victim) && md.drop {
+                            lines.add_negative(x, MEDIC_DROP_VALUE, true, LineKind::MedicDrop);
+                        }
                     }
+                    _ => (),
                 }
-                EventType::Kill(kill) => {
-                    if kill.attacker == player_id {
-                        // We don't care about headshot kills because it is already captured by the damage.
-                        if kill.weapon.starts_with("deflect") || kill.backstab {
-                            lines.add_positive(x, HEADSHOT_BACKSTAB_REFLECT_KILL_VALUE, true, HEADSHOT_BACKSTAB_REFLECT_COLOR);
-                            score += HEADSHOT_BACKSTAB_REFLECT_KILL_VALUE;
-                        }
+            }
+
+            batch.positive = lines.current_positive;
+            batch.negative = -lines.current_negative;
+
+            if batch.score > highlight_score {
+                let delta = mid_timestamp - start;
+                let tick = ((delta as f32) * 66.66666).round();
+                timeline.highlights.push(Highlight { x, timestamp: mid_timestamp, tick });
+            }
+
+            timeline.batches.push(batch);
+        }
+
+        timeline
+    }
+
+    pub fn x_at(&self, timestamp: i64) -> f32 {
+        let duration = (self.end - self.start) as f32;
+        let progress = (timestamp - self.start) as f32 / duration;
+        lerp(self.canvas.line_start(), self.canvas.line_end(), progress)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn draw_graph(filtered: &FilteredEvents, players: &[Player], events: &[Event], rounds: &[Round], batching: i64, style: &Style, graph_filename: &str, highlights_filename: &str) -> io::Result<()> {
+    println!("Making timeline for player: {}, batching: {}", filtered.player.name, batching);
+    let player_id = players.iter().position(|i| i.id == filtered.player.id).unwrap();
+
+    let timeline = Timeline::build(&filtered.events, &[player_id], batching, &style.canvas);
+    let title = format!("Player: {}", &filtered.player.name);
+    render_timeline(&timeline, &title, batching, rounds, &markers(players, events, &[player_id], style), style, graph_filename, highlights_filename)
+}
+
+// The combined contribution of everyone on a team.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_team_graph(team: Team, players: &[Player], events: &[Event], rounds: &[Round], batching: i64, style: &Style, graph_filename: &str, highlights_filename: &str) -> io::Result<()> {
+    println!("Making timeline for team: {:?}, batching: {}", team, batching);
+    let roster = team_roster(players, team);
+
+    let team_events = filter_events_for(&roster, events).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let timeline = Timeline::build(&team_events, &roster, batching, &style.canvas);
+    let title = format!("Team: {:?}", team);
+    render_timeline(&timeline, &title, batching, rounds, &markers(players, events, &roster, style), style, graph_filename, highlights_filename)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_timeline(
+    timeline: &Timeline,
+    title: &str,
+    batching: i64,
+    rounds: &[Round],
+    markers: &[Marker],
+    style: &Style,
+    graph_filename: &str,
+    highlights_filename: &str,
+) -> io::Result<()> {
+    let (canvas, scale) = (&style.canvas, style.canvas.scale);
+
+    let mut dt = DrawTarget::new(canvas.width as i32, canvas.height as i32);
+    dt.clear(style.theme.bg);
+
+    draw_rounds(&mut dt, style, |t| timeline.x_at(t), rounds);
+    timeline.lines.draw(&mut dt, style);
+    draw_time_axis(&mut dt, style, |t| timeline.x_at(t), timeline.match_start, timeline.end);
+
+    let mut highlights = String::from("Highlights:\n");
+    for (idx, highlight) in timeline.highlights.iter().enumerate() {
+        style.text(&mut dt, FONT_SIZE, &idx.to_string(), highlight.x, canvas.graph_height() - 20.0 * scale);
+
+        highlights += &format!("{}: tick={}", idx, highlight.tick);
+        if idx < timeline.highlights.len()-1 {
+            highlights += ", ";
+        }
+    }
+    highlights.push('\n');
+
+    let key: Vec<_> = KEY.iter().map(|(kind, label)| (kind.color(&style.theme), *label)).collect();
+    draw_key(&mut dt, style, &key);
+    draw_title(&mut dt, style, &format!("{}, batching: {}s, scale: {:.2}", title, batching, timeline.lines.global_y_scale));
+    draw_baseline(&mut dt, style);
+    draw_objectives(&mut dt, style, |t| timeline.x_at(t), markers);
+
+    // The graph first, so a failed draw doesn't leave highlights behind that stop the next run without --force.
+    dt.write_png(graph_filename)?;
+    fs::write(highlights_filename, highlights)
+}
+
+// Red's net contribution minus Blu's for each batch, so whoever was winning the fight sits on their side of the baseline.
+pub fn draw_momentum_graph(players: &[Player], events: &[Event], rounds: &[Round], batching: i64, style: &Style, graph_filename: &str) -> io::Result<()> {
+    println!("Making momentum timeline, batching: {}", batching);
+    let canvas = &style.canvas;
+
+    let mut dt = DrawTarget::new(canvas.width as i32, canvas.height as i32);
+    dt.clear(style.theme.bg);
+
+    let (red_roster, blu_roster) = (team_roster(players, Team::Red), team_roster(players, Team::Blu));
+    let team_events = |roster: &[usize]| filter_events_for(roster, events).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e));
+    let red = Timeline::build(&team_events(&red_roster)?, &red_roster, batching, canvas);
+    let blu = Timeline::build(&team_events(&blu_roster)?, &blu_roster, batching, canvas);
+
+    let (start, end) = (red.start.min(blu.start), red.end.max(blu.end));
+    let buckets = ((end - start) / batching + 1) as usize;
+    let momentum: Vec<f32> = bucket_net(&red, start, buckets, batching)
+        .iter()
+        .zip(bucket_net(&blu, start, buckets, batching))
+        .map(|(red, blu)| red - blu)
+        .collect();
+
+    let max = momentum.iter().fold(1.0_f32, |max, n| max.max(n.abs()));
+    let y_scale = (canvas.graph_height() * 0.5) / max;
+    let base_y = canvas.graph_height() * 0.5;
+    let x_at = |timestamp: i64| lerp(canvas.line_start(), canvas.line_end(), (timestamp - start) as f32 / (end - start) as f32);
+
+    draw_rounds(&mut dt, style, x_at, rounds);
+
+    for (bucket, value) in momentum.iter().enumerate() {
+        let from = start + bucket as i64 * batching;
+        let (x0, x1) = (x_at(from), x_at(from + batching).min(canvas.line_end()));
+        let color = if *value >= 0.0 { style.theme.red_team } else { style.theme.blu_team };
+
+        let mut pb = PathBuilder::new();
+        pb.rect(x0, base_y.min(base_y - value * y_scale), x1 - x0, (value * y_scale).abs());
+        dt.fill(&pb.finish(), &Source::Solid(color), &DRAW_OPTIONS);
+    }
+
+    draw_key(&mut dt, style, &[(style.theme.red_team, "red ahead"), (style.theme.blu_team, "blu ahead")]);
+    draw_title(&mut dt, style, &format!("Momentum, batching: {}s, scale: {:.2}", batching, y_scale));
+    draw_baseline(&mut dt, style);
+    draw_time_axis(&mut dt, style, x_at, red.match_start.min(blu.match_start), end);
+    // Only those who played, as spectators and the console coming and going or chatting isn't part of the match.
+    let playing = [red_roster, blu_roster].concat();
+    draw_objectives(&mut dt, style, x_at, &markers(players, events, &playing, style));
+
+    dt.write_png(graph_filename)?;
+    Ok(())
+}
+
+// Net contribution (positive minus negative) of several players on a shared time axis.
+pub fn draw_overlay(filtered: &[&FilteredEvents], players: &[Player], events: &[Event], rounds: &[Round], batching: i64, style: &Style, graph_filename: &str) -> io::Result<()> {
+    let names: Vec<&str> = filtered.iter().map(|f| f.player.name.as_str()).collect();
+    println!("Making overlay for players: {}, batching: {}", names.join(", "), batching);
+
+    let canvas = &style.canvas;
+
+    let mut dt = DrawTarget::new(canvas.width as i32, canvas.height as i32);
+    dt.clear(style.theme.bg);
+
+    let player_ids: Vec<usize> = filtered.iter().map(|f| players.iter().position(|i| i.id == f.player.id).unwrap()).collect();
+    let timelines: Vec<Timeline> = filtered.iter().zip(&player_ids).map(|(f, player_id)| {
+        Timeline::build(&f.events, &[*player_id], batching, canvas)
+    }).collect();
+
+    let start = timelines.iter().map(|t| t.start).min().unwrap();
+    let end = timelines.iter().map(|t| t.end).max().unwrap();
+    let buckets = ((end - start) / batching + 1) as usize;
+
+    let nets: Vec<Vec<f32>> = timelines.iter().map(|t| bucket_net(t, start, buckets, batching)).collect();
+
+    let max = nets.iter().flatten().fold(1.0_f32, |max, n| max.max(n.abs()));
+    let y_scale = (canvas.graph_height() * 0.5) / max;
+    let base_y = canvas.graph_height() * 0.5;
+    let x_at = |timestamp: i64| lerp(canvas.line_start(), canvas.line_end(), (timestamp - start) as f32 / (end - start) as f32);
+    // Each bucket is drawn at its middle.
+    let bucket_x = |bucket: usize| x_at(start + bucket as i64 * batching + batching / 2);
+
+    draw_rounds(&mut dt, style, x_at, rounds);
+
+    let mut key = vec![];
+    for (idx, net) in nets.iter().enumerate() {
+        let color = style.theme.overlay[idx % style.theme.overlay.len()];
+
+        let mut area = PathBuilder::new();
+        let mut line = PathBuilder::new();
+        area.move_to(bucket_x(0), base_y);
+        for (bucket, value) in net.iter().enumerate() {
+            let (x, y) = (bucket_x(bucket), base_y - value * y_scale);
+            area.line_to(x, y);
+            if bucket == 0 {
+                line.move_to(x, y);
+            } else {
+                line.line_to(x, y);
+            }
+        }
+        area.line_to(bucket_x(net.len() - 1), base_y);
+        area.close();
+
+        let fill = SolidSource::from_unpremultiplied_argb(40, color.r, color.g, color.b);
+        dt.fill(&area.finish(), &Source::Solid(fill), &DRAW_OPTIONS);
+        dt.stroke(&line.finish(), &Source::Solid(color), &style.stroke(STROKE_STYLE_OVERLAY), &DRAW_OPTIONS_TEXT);
+
+        key.push((color, names[idx]));
+    }
+
+    draw_key(&mut dt, style, &key);
+    draw_title(&mut dt, style, &format!("Net contribution, batching: {}s, scale: {:.2}", batching, y_scale));
+    draw_baseline(&mut dt, style);
+    draw_time_axis(&mut dt, style, x_at, timelines.iter().map(|t| t.match_start).min().unwrap(), end);
+    draw_objectives(&mut dt, style, x_at, &markers(players, events, &player_ids, style));
+
+    dt.write_png(graph_filename)?;
+    Ok(())
+}
+
+// A player's stats for one match on a trend chart.
+pub struct TrendPoint {
+    // Along the bottom, e.g. the date of the match.
+    pub label: String,
+    pub dpm: f32,
+    pub kad: f32,
+    pub highlights: usize,
+}
+
+// How a player's DPM, KA/D and highlight count changed from match to match, oldest first. They're nothing alike in
+// size, so each gets its own band scaled to its own maximum, with the values written next to each point.
+pub fn draw_trend(name: &str, points: &[TrendPoint], style: &Style, graph_filename: &str) -> io::Result<()> {
+    println!("Making trend for player: {}, matches: {}", name, points.len());
+    let (canvas, scale) = (&style.canvas, style.canvas.scale);
+
+    let mut dt = DrawTarget::new(canvas.width as i32, canvas.height as i32);
+    dt.clear(style.theme.bg);
+
+    let x_at = |idx: usize| match points.len() {
+        1 => (canvas.line_start() + canvas.line_end()) * 0.5,
+        // Clear of the labels on the left.
+        len => lerp(canvas.line_start() + 120.0 * scale, canvas.line_end() - 40.0 * scale, idx as f32 / (len - 1) as f32),
+    };
+
+    let series: [(&str, Vec<f32>, usize); 3] = [
+        ("dpm", points.iter().map(|p| p.dpm).collect(), 0),
+        ("ka/d", points.iter().map(|p| p.kad).collect(), 1),
+        ("highlights", points.iter().map(|p| p.highlights as f32).collect(), 0),
+    ];
+    let band = canvas.graph_height() / series.len() as f32;
+
+    for (idx, (label, values, decimals)) in series.iter().enumerate() {
+        let color = style.theme.overlay[idx % style.theme.overlay.len()];
+        // Leave room above the highest point for its value.
+        let (top, bottom) = (idx as f32 * band + 25.0 * scale, (idx + 1) as f32 * band - 10.0 * scale);
+        let max = values.iter().fold(f32::EPSILON, |max, v| max.max(*v));
+        let y_at = |value: f32| lerp(bottom, top, value / max);
+
+        if idx > 0 {
+            draw_line(&mut dt, style, canvas.line_start(), idx as f32 * band, canvas.line_end(), idx as f32 * band, style.theme.round);
+        }
+        style.text(&mut dt, FONT_SIZE, label, canvas.line_start(), idx as f32 * band + 15.0 * scale);
+
+        let mut line = PathBuilder::new();
+        for (point, value) in values.iter().enumerate() {
+            let (x, y) = (x_at(point), y_at(*value));
+            if point == 0 {
+                line.move_to(x, y);
+            } else {
+                line.line_to(x, y);
+            }
+
+            let mut dot = PathBuilder::new();
+            dot.arc(x, y, 3.0 * scale, 0.0, 2.0 * std::f32::consts::PI);
+            dt.fill(&dot.finish(), &Source::Solid(color), &DRAW_OPTIONS_TEXT);
+            style.text(&mut dt, AXIS_FONT_SIZE, &format!("{:.*}", decimals, value), x + 5.0 * scale, y - 5.0 * scale);
+        }
+        dt.stroke(&line.finish(), &Source::Solid(color), &style.stroke(STROKE_STYLE_OVERLAY), &DRAW_OPTIONS_TEXT);
+    }
 
-                        lines.add_positive(x, KILL_VALUE, true, KILL_COLOR);
-                        score += KILL_VALUE;
-                    } else if kill.victim == player_id {
-                        lines.add_negative(x, DEATH_VALUE, true, DEATH_COLOR);
+    let y = canvas.graph_height() + 2.0 * scale;
+    draw_line(&mut dt, style, canvas.line_start(), y, canvas.line_end(), y, style.theme.round);
+    for (idx, point) in points.iter().enumerate() {
+        let x = x_at(idx);
+        draw_line(&mut dt, style, x, y, x, y + 4.0 * scale, style.theme.fg);
+        style.text(&mut dt, AXIS_FONT_SIZE, &point.label, (x - 32.0 * scale).max(0.0), y + 16.0 * scale);
+    }
+
+    draw_title(&mut dt, style, &format!("Trend: {}, last {} matches", name, points.len()));
+
+    dt.write_png(graph_filename)?;
+    Ok(())
+}
+
+// Timelines' batches don't line up with each other, so sum them into fixed buckets before comparing.
+fn bucket_net(timeline: &Timeline, start: i64, buckets: usize, batching: i64) -> Vec<f32> {
+    let mut net = vec![0.0; buckets];
+    for batch in &timeline.batches {
+        net[((batch.timestamp - start) / batching) as usize] += batch.positive - batch.negative;
+    }
+    net
+}
+
+pub enum MarkerKind {
+    Capture(Team),
+    Uber(Team),
+    Drop,
+    // Someone on the team had the flag until `until`.
+    Carry { team: Team, until: i64 },
+    // The end of a stage, won by the team.
+    Stage(Team),
+    // Someone on the team said something.
+    Chat(Team),
+    // One of the players was off the server until `until`.
+    Away { until: i64 },
+}
+
+pub struct Marker {
+    pub timestamp: i64,
+    pub kind: MarkerKind,
+    // Shown above the marker, e.g. which point was captured.
+    pub label: Option<String>,
+}
+
+// Point captures, flags, ubers and medic drops for both teams, to relate spikes in the graph to the state of the game.
+// On maps with more than one point the captures are numbered, and flag captures are counted for each team, so the
+// progress through the match can be followed along the graph.
+pub fn objective_markers(players: &[Player], events: &[Event]) -> Vec<Marker> {
+    let numbered = events.iter().any(|e| matches!(&e.event, EventType::PointCaptured(pc) if pc.cp > 0));
+    let mut flag_caps = (0, 0);
+    let mut stages = 0;
+    let mut carrying: Option<(i64, Team)> = None;
+
+    let mut markers = vec![];
+    for e in events {
+        let (kind, label) = match &e.event {
+            EventType::PointCaptured(pc) => (MarkerKind::Capture(pc.team), Some(pc.cp + 1).filter(|_| numbered).map(|cp| cp.to_string())),
+            EventType::Flag(flag) => {
+                let team = players[flag.player].team;
+                match flag.action {
+                    FlagAction::PickedUp => {
+                        carrying = Some((e.timestamp, team));
+                        continue;
                     }
-                },
-                EventType::Hit(_hit) => {
-                    lines.add_positive(x, HIT_VALUE, false, SHOT_HIT_COLOR);
-                    score += HIT_VALUE;
-                }
-                EventType::MedicDeath(md) => {
-                    if md.attacker == player_id {
-                        if md.drop {
-                            lines.add_positive(x, MEDIC_DROP_VALUE, true, MEDIC_DROP_COLOR);
-                        } else {
-                            lines.add_positive(x, MEDIC_KILL_VALUE, true, MEDIC_KILL_COLOR);
+                    FlagAction::Dropped | FlagAction::Captured => {
+                        if let Some((from, team)) = carrying.take() {
+                            markers.push(Marker { timestamp: from, kind: MarkerKind::Carry { team, until: e.timestamp }, label: None });
+                        }
+                        if flag.action == FlagAction::Dropped {
+                            continue;
                         }
-                    } else if md.victim == player_id && md.drop {
-                        lines.add_negative(x, MEDIC_DROP_VALUE, true, MEDIC_DROP_COLOR);
+
+                        let count = if team == Team::Red { &mut flag_caps.0 } else { &mut flag_caps.1 };
+                        *count += 1;
+                        (MarkerKind::Capture(team), Some(count.to_string()))
                     }
+                    FlagAction::Defended => continue,
                 }
-                _ => (),
             }
+            EventType::StageWin(stage) => {
+                stages += 1;
+                (MarkerKind::Stage(stage.team), Some(format!("stage {}", stages)))
+            }
+            EventType::ChargeDeployed(charge) => (MarkerKind::Uber(players[charge.player].team), None),
+            EventType::MedicDeath(md) if md.drop => (MarkerKind::Drop, None),
+            _ => continue,
+        };
+
+        markers.push(Marker { timestamp: e.timestamp, kind, label });
+    }
+
+    markers
+}
+
+// Chat from the players being graphed, in all chat or their team's.
+pub fn chat_markers(players: &[Player], events: &[Event], player_ids: &[usize]) -> Vec<Marker> {
+    events.iter().filter_map(|e| match &e.event {
+        EventType::Say(say) if player_ids.contains(&say.player) => {
+            Some(Marker { timestamp: e.timestamp, kind: MarkerKind::Chat(players[say.player].team), label: None })
         }
+        _ => None,
+    }).collect()
+}
 
-        if score > 250.0 {
-            let delta = mid_timestamp - start;
-            let tick = ((delta as f32) * 66.66666).round();
-            noteworthy.push((x, tick));
+// Everything marked on the graph of `player_ids`: the objectives, when any of them were disconnected and, with --chat,
+// what they said.
+fn markers(players: &[Player], events: &[Event], player_ids: &[usize], style: &Style) -> Vec<Marker> {
+    let mut markers = objective_markers(players, events);
+    for player_id in player_ids {
+        markers.extend(absences(*player_id, events).into_iter().map(|(from, until)| {
+            Marker { timestamp: from, kind: MarkerKind::Away { until }, label: None }
+        }));
+    }
+    if style.chat {
+        markers.extend(chat_markers(players, events, player_ids));
+    }
+    markers
+}
+
+fn team_color(theme: &Theme, team: Team) -> SolidSource {
+    match team {
+        Team::Red => theme.red_team,
+        Team::Blu => theme.blu_team,
+        _ => theme.fg,
+    }
+}
+
+// Round boundaries as vertical lines, with the end of the round in the winner's colour and overtime shaded.
+fn draw_rounds(dt: &mut DrawTarget, style: &Style, x_at: impl Fn(i64) -> f32, rounds: &[Round]) {
+    let theme = &style.theme;
+    let height = style.canvas.graph_height();
+    let overtime = SolidSource::from_unpremultiplied_argb(30, theme.overtime.r, theme.overtime.g, theme.overtime.b);
+
+    for round in rounds {
+        if let Some(from) = round.overtime {
+            let (x0, x1) = (x_at(from), x_at(round.end));
+            let mut pb = PathBuilder::new();
+            pb.rect(x0, 0.0, x1 - x0, height);
+            dt.fill(&pb.finish(), &Source::Solid(overtime), &DRAW_OPTIONS);
+        }
+
+        let x = x_at(round.start);
+        draw_line(dt, style, x, 0.0, x, height, theme.round);
+
+        let x = x_at(round.end);
+        let color = round.winner.map(|team| team_color(theme, team)).unwrap_or(theme.round);
+        draw_line(dt, style, x, 0.0, x, height, color);
+    }
+}
+
+// Game time in mm:ss along the bottom of the graph, spacing the ticks out so the labels don't overlap.
+fn draw_time_axis(dt: &mut DrawTarget, style: &Style, x_at: impl Fn(i64) -> f32, match_start: i64, end: i64) {
+    let (canvas, scale) = (&style.canvas, style.canvas.scale);
+    let y = canvas.graph_height() + 2.0 * scale;
+    draw_line(dt, style, canvas.line_start(), y, canvas.line_end(), y, style.theme.round);
+
+    let minute_width = x_at(match_start + 60) - x_at(match_start);
+    let step = [1, 2, 5, 10, 15, 30]
+        .iter()
+        .find(|step| **step as f32 * minute_width >= 60.0 * scale)
+        .copied()
+        .unwrap_or(60) * 60;
+
+    let mut seconds = 0;
+    while match_start + seconds <= end {
+        let x = x_at(match_start + seconds);
+        draw_line(dt, style, x, y, x, y + 4.0 * scale, style.theme.fg);
+        style.text(dt, AXIS_FONT_SIZE, &format_game_time(seconds), (x - 16.0 * scale).max(0.0), y + 16.0 * scale);
+        seconds += step;
+    }
+}
+
+// Objective markers sit on the baseline, drawn on top of everything else.
+fn draw_objectives(dt: &mut DrawTarget, style: &Style, x_at: impl Fn(i64) -> f32, markers: &[Marker]) {
+    let (canvas, scale) = (&style.canvas, style.canvas.scale);
+    let y = canvas.graph_height() * 0.5;
+    for marker in markers {
+        let x = x_at(marker.timestamp);
+        match &marker.kind {
+            // Just below the baseline, so the other markers stay visible.
+            MarkerKind::Carry { team, until } => {
+                let y = y + 6.0 * scale;
+                draw_line(dt, style, x, y, x_at(*until), y, team_color(&style.theme, *team));
+            }
+            // Shaded like overtime, but faintly so the events underneath still show.
+            MarkerKind::Away { until } => {
+                let away = SolidSource::from_unpremultiplied_argb(24, style.theme.fg.r, style.theme.fg.g, style.theme.fg.b);
+                let mut pb = PathBuilder::new();
+                pb.rect(x, 0.0, x_at(*until) - x, canvas.graph_height());
+                dt.fill(&pb.finish(), &Source::Solid(away), &DRAW_OPTIONS);
+            }
+            // Along the top, out of the way of the objectives.
+            MarkerKind::Chat(_) => draw_marker(dt, style, x, 6.0 * scale, &marker.kind),
+            kind => draw_marker(dt, style, x, y, kind),
+        }
+        // Stages end on a capture, so their labels go underneath to keep clear of the point's.
+        if let Some(label) = &marker.label {
+            let label_y = if matches!(marker.kind, MarkerKind::Stage(_)) { y + 20.0 * scale } else { y - 10.0 * scale };
+            style.text(dt, AXIS_FONT_SIZE, label, x - 3.0 * scale, label_y);
         }
     }
 
-    lines.draw(&mut dt);
-
-    // TODO: return result.
-    let file = File::create(highlights_filename).expect("Failed to create highlights file.");
-    let mut highlights = BufWriter::new(file);
-    writeln!(&mut highlights, "Highlights:").unwrap();
-    for (idx, (x, tick)) in noteworthy.iter().enumerate() {
-        dt.draw_text(
-            &font,
-            14.0,
-            &idx.to_string(),
-            Point::new(*x, HEIGHT - 20.0),
-            &Source::Solid(FG_COLOR),
-            &DRAW_OPTIONS_TEXT
-        );
-
-        write!(&mut highlights, "{}: tick={}", idx, tick).unwrap();
-        if idx < noteworthy.len()-1 {
-            write!(&mut highlights, ", ").unwrap();
+    // Key, next to the title.
+    let mut key = vec![
+        (MarkerKind::Capture(Team::Unknown), "capture"),
+        (MarkerKind::Uber(Team::Unknown), "uber"),
+        (MarkerKind::Drop, "drop"),
+    ];
+    if markers.iter().any(|m| matches!(m.kind, MarkerKind::Carry { .. })) {
+        key.push((MarkerKind::Carry { team: Team::Unknown, until: 0 }, "flag"));
+    }
+    if markers.iter().any(|m| matches!(m.kind, MarkerKind::Chat(_))) {
+        key.push((MarkerKind::Chat(Team::Unknown), "chat"));
+    }
+    if markers.iter().any(|m| matches!(m.kind, MarkerKind::Away { .. })) {
+        key.push((MarkerKind::Away { until: 0 }, "disconnected"));
+    }
+    for (idx, (kind, label)) in key.iter().enumerate() {
+        let x = (300.0 + idx as f32 * 100.0) * scale;
+        let y = canvas.height - 34.0 * scale;
+        match kind {
+            MarkerKind::Carry { .. } => draw_line(dt, style, x - 6.0 * scale, y, x + 6.0 * scale, y, style.theme.fg),
+            MarkerKind::Away { .. } => {
+                let mut pb = PathBuilder::new();
+                pb.rect(x - 4.0 * scale, y - 4.0 * scale, 8.0 * scale, 8.0 * scale);
+                let away = SolidSource::from_unpremultiplied_argb(96, style.theme.fg.r, style.theme.fg.g, style.theme.fg.b);
+                dt.fill(&pb.finish(), &Source::Solid(away), &DRAW_OPTIONS);
+            }
+            kind => draw_marker(dt, style, x, y, kind),
         }
+        style.text(dt, FONT_SIZE, label, x + 10.0 * scale, canvas.height - 29.0 * scale);
     }
-    writeln!(&mut highlights).unwrap();
-
-    // Draw key.
-    draw_line(&mut dt, 20.0, REAL_HEIGHT - 10.0, 60.0, REAL_HEIGHT - 10.0, DAMAGE_COLOR);
-    draw_line(&mut dt, 20.0, REAL_HEIGHT - 20.0, 60.0, REAL_HEIGHT - 20.0, HEAL_COLOR);
-    draw_line(&mut dt, 20.0, REAL_HEIGHT - 30.0, 60.0, REAL_HEIGHT - 30.0, HEADSHOT_BACKSTAB_REFLECT_COLOR);
-    draw_line(&mut dt, 20.0, REAL_HEIGHT - 40.0, 60.0, REAL_HEIGHT - 40.0, AIRSHOT_COLOR);
-    draw_line(&mut dt, 20.0, REAL_HEIGHT - 50.0, 60.0, REAL_HEIGHT - 50.0, KILL_COLOR);
-    draw_line(&mut dt, 20.0, REAL_HEIGHT - 60.0, 60.0, REAL_HEIGHT - 60.0, MEDIC_KILL_COLOR);
-    draw_line(&mut dt, 20.0, REAL_HEIGHT - 70.0, 60.0, REAL_HEIGHT - 70.0, MEDIC_DROP_COLOR);
-    draw_line(&mut dt, 20.0, REAL_HEIGHT - 80.0, 60.0, REAL_HEIGHT - 80.0, DEATH_COLOR);
-    dt.draw_text(&font, 14.0, "damage", Point::new(70.0, REAL_HEIGHT - 5.0), &Source::Solid(FG_COLOR), &DRAW_OPTIONS_TEXT);
-    dt.draw_text(&font, 14.0, "healing", Point::new(70.0, REAL_HEIGHT - 15.0), &Source::Solid(FG_COLOR), &DRAW_OPTIONS_TEXT);
-    dt.draw_text(&font, 14.0, "headshot/backstab/reflect", Point::new(70.0, REAL_HEIGHT - 25.0), &Source::Solid(FG_COLOR), &DRAW_OPTIONS_TEXT);
-    dt.draw_text(&font, 14.0, "airshot", Point::new(70.0, REAL_HEIGHT - 35.0), &Source::Solid(FG_COLOR), &DRAW_OPTIONS_TEXT);
-    dt.draw_text(&font, 14.0, "kill", Point::new(70.0, REAL_HEIGHT - 45.0), &Source::Solid(FG_COLOR), &DRAW_OPTIONS_TEXT);
-    dt.draw_text(&font, 14.0, "medic_kill", Point::new(70.0, REAL_HEIGHT - 55.0), &Source::Solid(FG_COLOR), &DRAW_OPTIONS_TEXT);
-    dt.draw_text(&font, 14.0, "medic_drop", Point::new(70.0, REAL_HEIGHT - 65.0), &Source::Solid(FG_COLOR), &DRAW_OPTIONS_TEXT);
-    dt.draw_text(&font, 14.0, "death", Point::new(70.0, REAL_HEIGHT - 75.0), &Source::Solid(FG_COLOR), &DRAW_OPTIONS_TEXT);
-
-    dt.draw_text(
-        &font,
-        14.0,
-        &format!("Player: {}, batching: {}s, scale: {:.2}", &filtered.player.name, batching, lines.global_y_scale),
-        Point::new(300.0, REAL_HEIGHT - 10.0),
-        &Source::Solid(FG_COLOR),
-        &DRAW_OPTIONS_TEXT
-    );
+}
+
+fn draw_marker(dt: &mut DrawTarget, style: &Style, x: f32, y: f32, kind: &MarkerKind) {
+    let size = style.canvas.scale;
+    let mut pb = PathBuilder::new();
+    let color = match kind {
+        MarkerKind::Capture(team) => {
+            pb.rect(x - 3.0 * size, y - 3.0 * size, 6.0 * size, 6.0 * size);
+            team_color(&style.theme, *team)
+        }
+        MarkerKind::Uber(team) => {
+            pb.arc(x, y, 3.5 * size, 0.0, 2.0 * std::f32::consts::PI);
+            team_color(&style.theme, *team)
+        }
+        MarkerKind::Drop => {
+            pb.move_to(x, y - 4.0 * size);
+            pb.line_to(x + 4.0 * size, y);
+            pb.line_to(x, y + 4.0 * size);
+            pb.line_to(x - 4.0 * size, y);
+            pb.close();
+            style.theme.medic_drop
+        }
+        MarkerKind::Stage(team) => {
+            pb.rect(x - size, y - 8.0 * size, 2.0 * size, 16.0 * size);
+            team_color(&style.theme, *team)
+        }
+        MarkerKind::Chat(team) => {
+            pb.move_to(x - 3.0 * size, y - 3.0 * size);
+            pb.line_to(x + 3.0 * size, y - 3.0 * size);
+            pb.line_to(x, y + 3.0 * size);
+            pb.close();
+            team_color(&style.theme, *team)
+        }
+        MarkerKind::Carry { .. } | MarkerKind::Away { .. } => return,
+    };
+
+    dt.fill(&pb.finish(), &Source::Solid(color), &DRAW_OPTIONS_TEXT);
+}
 
+// Coloured lines and their labels in the bottom left, from bottom to top.
+fn draw_key(dt: &mut DrawTarget, style: &Style, key: &[(SolidSource, &str)]) {
+    let (height, scale) = (style.canvas.height, style.canvas.scale);
+    for (idx, (color, label)) in key.iter().enumerate() {
+        let offset = idx as f32 * 10.0 * scale;
+        draw_line(dt, style, 20.0 * scale, height - 10.0 * scale - offset, 60.0 * scale, height - 10.0 * scale - offset, *color);
+        style.text(dt, FONT_SIZE, label, 70.0 * scale, height - 5.0 * scale - offset);
+    }
+}
+
+fn draw_title(dt: &mut DrawTarget, style: &Style, title: &str) {
+    let (height, scale) = (style.canvas.height, style.canvas.scale);
+    style.text(dt, FONT_SIZE, title, 300.0 * scale, height - 10.0 * scale);
+    if let Some(caption) = &style.caption {
+        style.text(dt, FONT_SIZE, caption, 300.0 * scale, height - 48.0 * scale);
+    }
+}
+
+fn draw_baseline(dt: &mut DrawTarget, style: &Style) {
+    let canvas = &style.canvas;
     let mut pb = PathBuilder::new();
-    pb.move_to(line_start, HEIGHT * 0.5);
-    pb.line_to(line_end, HEIGHT * 0.5);
+    pb.move_to(canvas.line_start(), canvas.graph_height() * 0.5);
+    pb.line_to(canvas.line_end(), canvas.graph_height() * 0.5);
     let path = pb.finish();
     dt.stroke(
         &path,
-        &Source::Solid(FG_COLOR),
-        &STROKE_STYLE_BASELINE,
+        &Source::Solid(style.theme.fg),
+        &style.stroke(STROKE_STYLE_BASELINE),
         &DRAW_OPTIONS
     );
+}
+
+// Shipped with the binary, so graphs can still be drawn on servers without any fonts installed.
+static BUNDLED_FONT: &[u8] = include_bytes!("../assets/DejaVuSansMono.ttf");
 
-    dt.write_png(graph_filename).unwrap();
+// The font at `path` if given, otherwise the system's monospace font, falling back to the bundled one.
+pub fn load_font(path: Option<&str>) -> Result<Font, String> {
+    if let Some(path) = path {
+        return Font::from_path(path, 0).map_err(|e| format!("{}: {}", path, e));
+    }
+
+    let system = SystemSource::new()
+        .select_best_match(&[FamilyName::Monospace], &Properties::new())
+        .map_err(|e| e.to_string())
+        .and_then(|handle| handle.load().map_err(|e| e.to_string()));
+
+    match system {
+        Ok(font) => Ok(font),
+        Err(e) => {
+            // Each batch job loads its own font, so only say so once.
+            static NOTICE: Once = Once::new();
+            NOTICE.call_once(|| eprintln!("No system monospace font ({}), using the bundled font.", e));
+            Font::from_bytes(Arc::new(BUNDLED_FONT.to_vec()), 0).map_err(|e| e.to_string())
+        }
+    }
 }
 
 fn lerp(start: f32, end: f32, val: f32) -> f32 {
@@ -346,7 +988,7 @@ fn lerp(start: f32, end: f32, val: f32) -> f32 {
     }
 }
 
-fn draw_line(dt: &mut DrawTarget, start_x: f32, start_y: f32, end_x: f32, end_y: f32, color: SolidSource) {
+fn draw_line(dt: &mut DrawTarget, style: &Style, start_x: f32, start_y: f32, end_x: f32, end_y: f32, color: SolidSource) {
     let mut pb = PathBuilder::new();
     pb.move_to(start_x, start_y);
     pb.line_to(end_x, end_y);
@@ -354,12 +996,12 @@ fn draw_line(dt: &mut DrawTarget, start_x: f32, start_y: f32, end_x: f32, end_y:
     dt.stroke(
         &path,
         &Source::Solid(color),
-        &STROKE_STYLE_EVENTS,
+        &style.stroke(STROKE_STYLE_EVENTS),
         &DRAW_OPTIONS
     );
 }
 
-fn draw_cap(dt: &mut DrawTarget, pos_x: f32, pos_y: f32, size: f32, color: SolidSource) {
+fn draw_cap(dt: &mut DrawTarget, style: &Style, pos_x: f32, pos_y: f32, size: f32, color: SolidSource) {
     let mut pb = PathBuilder::new();
     pb.move_to(pos_x - size, pos_y);
     pb.line_to(pos_x + size, pos_y);
@@ -367,7 +1009,157 @@ fn draw_cap(dt: &mut DrawTarget, pos_x: f32, pos_y: f32, size: f32, color: Solid
     dt.stroke(
         &path,
         &Source::Solid(color),
-        &STROKE_STYLE_EVENTS,
+        &style.stroke(STROKE_STYLE_EVENTS),
         &DRAW_OPTIONS
     );
 }
+
+// Seconds since the start of the match as "mm:ss".
+pub fn format_game_time(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: i64, event: EventType) -> Event {
+        Event::new(timestamp, event)
+    }
+
+    fn kill(timestamp: i64, attacker: usize, victim: usize) -> Event {
+        at(timestamp, EventType::Kill(KillEvent { attacker, victim, weapon: "scattergun".to_owned(), headshot: false, backstab: false }))
+    }
+
+    // A round from 0 to 600, with `events` in between.
+    fn round(events: Vec<Event>) -> Vec<Event> {
+        let mut round = vec![at(0, EventType::RoundStart)];
+        round.extend(events);
+        round.push(at(600, EventType::GameOver));
+        round
+    }
+
+    fn highlights(events: &[Event], player_ids: &[usize]) -> Vec<i64> {
+        Timeline::build(events, player_ids, 10, &Canvas::default()).highlights.iter().map(|h| h.timestamp).collect()
+    }
+
+    fn players() -> Vec<Player> {
+        let player = |name: &str, team: Team| Player { name: name.to_owned(), id: format!("[U:1:{}]", name.len()), team, aliases: vec![] };
+        vec![player("red", Team::Red), player("blu", Team::Blu), player("red medic", Team::Red)]
+    }
+
+    fn captured(timestamp: i64, team: Team, cp: u32) -> Event {
+        at(timestamp, EventType::PointCaptured(PointCapturedEvent { team, cp, name: format!("#cp{}", cp), cappers: vec![] }))
+    }
+
+    fn flag(timestamp: i64, player: usize, action: FlagAction) -> Event {
+        at(timestamp, EventType::Flag(FlagEvent { player, action }))
+    }
+
+    // Each marker as "timestamp kind label", to compare whole lists at once.
+    fn describe(markers: &[Marker]) -> Vec<String> {
+        markers.iter().map(|m| {
+            let kind = match &m.kind {
+                MarkerKind::Capture(team) => format!("capture {:?}", team),
+                MarkerKind::Uber(team) => format!("uber {:?}", team),
+                MarkerKind::Drop => "drop".to_owned(),
+                MarkerKind::Carry { team, until } => format!("carry {:?} until {}", team, until),
+                MarkerKind::Stage(team) => format!("stage {:?}", team),
+                MarkerKind::Chat(team) => format!("chat {:?}", team),
+                MarkerKind::Away { until } => format!("away until {}", until),
+            };
+            format!("{} {}{}", m.timestamp, kind, m.label.as_ref().map_or(String::new(), |l| format!(" {}", l)))
+        }).collect()
+    }
+
+    #[test]
+    fn point_captures() {
+        // On koth there's only one point, so there's nothing to number.
+        let events = [captured(100, Team::Red, 0), captured(200, Team::Blu, 0)];
+        assert_eq!(describe(&objective_markers(&players(), &events)), ["100 capture Red", "200 capture Blu"]);
+
+        let events = [captured(100, Team::Red, 2), captured(200, Team::Red, 3), captured(300, Team::Blu, 0)];
+        assert_eq!(describe(&objective_markers(&players(), &events)), ["100 capture Red 3", "200 capture Red 4", "300 capture Blu 1"]);
+    }
+
+    #[test]
+    fn flag_captures() {
+        let events = [
+            flag(100, 0, FlagAction::PickedUp), flag(110, 0, FlagAction::Dropped),
+            flag(120, 1, FlagAction::Defended),
+            flag(130, 2, FlagAction::PickedUp), flag(150, 2, FlagAction::Captured),
+            flag(200, 1, FlagAction::PickedUp), flag(230, 1, FlagAction::Captured),
+            flag(300, 0, FlagAction::PickedUp), flag(310, 0, FlagAction::Captured),
+        ];
+        assert_eq!(describe(&objective_markers(&players(), &events)), [
+            "100 carry Red until 110",
+            "130 carry Red until 150", "150 capture Red 1",
+            "200 carry Blu until 230", "230 capture Blu 1",
+            "300 carry Red until 310", "310 capture Red 2",
+        ]);
+    }
+
+    #[test]
+    fn stages() {
+        let stage = |timestamp: i64, team: Team| at(timestamp, EventType::StageWin(StageWinEvent { team, stage: "round_a".to_owned() }));
+        let events = [
+            stage(100, Team::Blu),
+            at(150, EventType::ChargeDeployed(ChargeDeployedEvent { player: 2, medigun: "medigun".to_owned() })),
+            at(160, EventType::MedicDeath(MedicDeathEvent { attacker: 1, victim: 2, drop: true })),
+            at(170, EventType::MedicDeath(MedicDeathEvent { attacker: 1, victim: 2, drop: false })),
+            stage(200, Team::Red),
+        ];
+        assert_eq!(describe(&objective_markers(&players(), &events)), ["100 stage Blu stage 1", "150 uber Red", "160 drop", "200 stage Red stage 2"]);
+    }
+
+    #[test]
+    fn chat() {
+        let say = |timestamp: i64, player: usize, team_only: bool| at(timestamp, EventType::Say(SayEvent { player, text: "gg".to_owned(), team_only }));
+        let events = [say(100, 0, false), say(110, 1, true), say(120, 2, true), say(130, 0, true)];
+        assert_eq!(describe(&chat_markers(&players(), &events, &[0, 1])), ["100 chat Red", "110 chat Blu", "130 chat Red"]);
+        assert!(chat_markers(&players(), &events, &[]).is_empty());
+    }
+
+    #[test]
+    fn buckets() {
+        let events = round(vec![kill(10, 0, 1), kill(12, 1, 0), kill(35, 0, 1)]);
+        let timeline = Timeline::build(&events, &[0], 10, &Canvas::default());
+        assert_eq!(timeline.start, -5);
+
+        // Kills at 10 and 12 are in the same batch, which lands in the bucket from 5 to 15.
+        let buckets = ((timeline.end - timeline.start) / 10 + 1) as usize;
+        let net = bucket_net(&timeline, timeline.start, buckets, 10);
+        assert_eq!(net.len(), 61);
+        assert_eq!(net[..5], [0.0, KILL_VALUE - DEATH_VALUE, 0.0, 0.0, KILL_VALUE]);
+        assert!(net[5..].iter().all(|n| *n == 0.0));
+
+        // A timeline that starts later shifts along the shared buckets.
+        let net = bucket_net(&timeline, timeline.start - 20, buckets + 2, 10);
+        assert_eq!(net[..7], [0.0, 0.0, 0.0, KILL_VALUE - DEATH_VALUE, 0.0, 0.0, KILL_VALUE]);
+    }
+
+    #[test]
+    fn player_highlights() {
+        let events = round(vec![
+            kill(100, 0, 1), kill(101, 0, 2),
+            kill(200, 0, 1), kill(201, 0, 2), kill(202, 0, 3),
+            // Only the player's own kills count.
+            kill(300, 1, 0), kill(301, 1, 2), kill(302, 1, 3),
+        ]);
+        assert_eq!(highlights(&events, &[0]), [201]);
+    }
+
+    #[test]
+    fn team_highlights() {
+        // Three kills are a highlight for one player, but not for a team of four.
+        let events = round(vec![
+            kill(100, 0, 4), kill(101, 1, 5), kill(102, 2, 6),
+            kill(200, 0, 4), kill(201, 1, 5), kill(202, 2, 6), kill(203, 3, 7), kill(204, 0, 5), kill(205, 1, 6),
+        ]);
+        assert_eq!(highlights(&events, &[0, 1, 2, 3]), [202]);
+        assert!(highlights(&events, &[0]).is_empty());
+        assert!(highlights(&events, &[]).is_empty());
+        assert!(highlights(&round(vec![]), &[]).is_empty());
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    // Bad command line arguments.
+    Usage(String),
+    // A log (or other input such as a theme or font) couldn't be loaded.
+    Input { input: String, reason: String },
+    // The player asked for isn't in the log, with the closest names that are.
+    PlayerNotFound { player: String, suggestions: Vec<String> },
+    // Writing a graph, report or export failed.
+    Output { output: String, reason: String },
+}
+
+impl Error {
+    pub fn usage(msg: impl Into<String>) -> Self {
+        Error::Usage(msg.into())
+    }
+
+    pub fn input(input: impl Into<String>, reason: impl ToString) -> Self {
+        Error::Input { input: input.into(), reason: reason.to_string() }
+    }
+
+    pub fn output(output: impl Into<String>, reason: impl ToString) -> Self {
+        Error::Output { output: output.into(), reason: reason.to_string() }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Output { .. } => 1,
+            Error::Usage(_) => 2,
+            Error::Input { .. } => 3,
+            Error::PlayerNotFound { .. } => 4,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(msg) => write!(f, "{}", msg),
+            Error::Input { input, reason } => write!(f, "failed to load {}: {}", input, reason),
+            Error::PlayerNotFound { player, suggestions } => {
+                write!(f, "couldn't find a matching player for {} in the given log", player)?;
+                if !suggestions.is_empty() {
+                    write!(f, ", did you mean: {}?", suggestions.join(", "))?;
+                }
+                Ok(())
+            }
+            Error::Output { output, reason } => write!(f, "failed to write {}: {}", output, reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
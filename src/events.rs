@@ -1,37 +1,14 @@
-use regex::Regex;
-use chrono::offset::Utc;
-use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead};
+use std::ops::Range;
 
-use crate::*;
+use chrono::NaiveDate;
+use serde::Serialize;
 
-lazy_static! {
-    // "Sexy Turtle<17><[U:1:296600241]><Red>" triggered "damage" against "calski<26><[U:1:98109542]><Blue>" (*damage* "*13*") (*weapon* "*shotgun_primary*")
-    static ref PROPERTIES: Regex = Regex::new(r#"\((\w{1,60}) "([^"]{1,60})"\)"#).unwrap();
-    // "*Sexy Turtle<17><[U:1:296600241]><Red>*" triggered "damage" against "*calski<26><[U:1:98109542]><Blue>*" (damage "13") (weapon "shotgun_primary")
-    static ref DAMAGED: Regex = Regex::new(r#"^"(?P<attacker>.+?)" triggered "damage" against "(?P<victim>.+?)"[\s|$]"#).unwrap();
-    // "tal<11><[U:1:91618645]><Red>" triggered "healed" against "Flow<14><[U:1:152978378]><Red>" (healing "32")
-    static ref HEALED: Regex = Regex::new(r#"^"(?P<player>.+?)" triggered "healed" against "(?P<target>.+?)"[\s|$]"#).unwrap();
-    // "*ozy<20><[U:1:71235035]><Red>*" killed "*lizrd-wizrd<10><[U:1:320890290]><Blue>*" with "*quake_rl*" (attacker_position "-2481 725 201") (victim_position "-2537 832 128")
-    static ref KILLED: Regex = Regex::new(r#"^"(?P<attacker>.+?)" killed "(?P<victim>.+?)" with "(?P<weapon>.+?)""#).unwrap();
-    // "*BONKITUP123<13><[U:1:105391228]><Red>*" triggered "shot_fired" (weapon "tf_projectile_pipe_remote")
-    static ref FIRED: Regex = Regex::new(r#"^"(?P<player>.+?)" triggered "shot_fired""#).unwrap();
-    // "*BONKITUP123<13><[U:1:105391228]><Red>*" triggered "shot_hit" (weapon "tf_projectile_pipe_remote")
-    static ref HIT: Regex = Regex::new(r#"^"(?P<player>.+?)" triggered "shot_hit""#).unwrap();
-    // "*oh no<4><[U:1:83248160]><Red>*" changed role to "*heavyweapons*"
-    static ref CHANGEDCLASS: Regex = Regex::new(r#"^"(?P<player>.+?)" changed role to "(?P<role>.+?)""#).unwrap();
-    // "*[VIP] Zach<8><[U:1:250686100]><Blue>*" triggered "medic_death" against "*tal<11><[U:1:91618645]><Red>*" (healing "0") (ubercharge "0")
-    static ref MEDICDIED: Regex = Regex::new(r#"^"(?P<attacker>.+?)" triggered "medic_death" against "(?P<victim>.+?)"[\s|$]"#).unwrap();
-    // not done
-    static ref SAY: Regex = Regex::new(r#"^"(?P<player>.+?)" say "(?P<message>.{1,160}?)"$"#).unwrap();
-    // not done
-    static ref SAY_TEAM: Regex = Regex::new(r#"^"(?P<player>.+?)" say_team "(?P<message>.{1,160}?)"$"#).unwrap();
-    // note done
-    static ref ROUND_START: Regex = Regex::new(r#"^World triggered "Round_Start""#).unwrap();
-    //static ref ROUND_END: Regex = Regex::new(r#"^World triggered "Round_Start""#).unwrap();
-    static ref GAME_OVER: Regex = Regex::new(r#"^World triggered "Game_Over""#).unwrap();
-}
+use crate::error::Error;
+use crate::*;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DamageEvent {
     pub attacker: usize,
     pub victim: usize,
@@ -41,14 +18,14 @@ pub struct DamageEvent {
     pub airshot: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct HealEvent {
     pub healer: usize,
     pub target: usize,
     pub healing: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct KillEvent {
     pub attacker: usize,
     pub victim: usize,
@@ -57,40 +34,115 @@ pub struct KillEvent {
     pub backstab: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+pub struct AssistEvent {
+    pub assister: usize,
+    pub victim: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct FiredEvent {
     pub player: usize,
     pub weapon: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct HitEvent {
     pub player: usize,
     pub weapon: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ChangeClassEvent {
     pub player: usize,
     pub class: Class,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+pub struct SpawnEvent {
+    pub player: usize,
+    pub class: Class,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct MedicDeathEvent {
     pub attacker: usize,
     pub victim: usize,
     pub drop: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SayEvent {
     pub player: usize,
     pub text: String,
+    // say_team, only seen by the player's team.
+    pub team_only: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+pub struct PointCapturedEvent {
+    pub team: Team,
+    pub cp: u32,
+    pub name: String,
+    pub cappers: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlagAction {
+    PickedUp,
+    Dropped,
+    Captured,
+    // Returned, or the carrier killed.
+    Defended,
+}
+
+// CTF's intelligence, or the ball in BBall.
+#[derive(Debug, Clone, Serialize)]
+pub struct FlagEvent {
+    pub player: usize,
+    pub action: FlagAction,
+}
+
+// The end of a stage on multi-stage maps, e.g. each of pl_badwater's or cp_dustbowl's. The round carries on into the next one.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageWinEvent {
+    pub team: Team,
+    pub stage: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectedEvent {
+    pub player: usize,
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DisconnectedEvent {
+    pub player: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidatedEvent {
+    pub player: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EnteredEvent {
+    pub player: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChargeDeployedEvent {
+    pub player: usize,
+    pub medigun: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Event {
     pub timestamp: i64,
+    #[serde(flatten)]
     pub event: EventType,
 }
 
@@ -101,281 +153,836 @@ impl Event {
             event,
         }
     }
+
+    // Everyone taking part in the event.
+    pub fn players(&self) -> Vec<usize> {
+        match &self.event {
+            EventType::Damage(e) => vec![e.attacker, e.victim],
+            EventType::Heal(e) => vec![e.healer, e.target],
+            EventType::Fired(e) => vec![e.player],
+            EventType::Hit(e) => vec![e.player],
+            EventType::Kill(e) => vec![e.attacker, e.victim],
+            EventType::Assist(e) => vec![e.assister, e.victim],
+            EventType::ChangeClass(e) => vec![e.player],
+            EventType::Spawn(e) => vec![e.player],
+            EventType::MedicDeath(e) => vec![e.attacker, e.victim],
+            EventType::Say(e) => vec![e.player],
+            EventType::PointCaptured(e) => e.cappers.clone(),
+            EventType::Flag(e) => vec![e.player],
+            EventType::ChargeDeployed(e) => vec![e.player],
+            EventType::Connected(e) => vec![e.player],
+            EventType::Disconnected(e) => vec![e.player],
+            EventType::Validated(e) => vec![e.player],
+            EventType::Entered(e) => vec![e.player],
+            EventType::StageWin(_) | EventType::RoundStart | EventType::RoundWin(_) | EventType::RoundOvertime | EventType::GameOver | EventType::TournamentRestart => vec![],
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+// Serialised as its name in `type`, with the event itself in `data`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum EventType {
     Damage(DamageEvent),
     Heal(HealEvent),
     Fired(FiredEvent),
     Hit(HitEvent),
     Kill(KillEvent),
+    Assist(AssistEvent),
     ChangeClass(ChangeClassEvent),
+    Spawn(SpawnEvent),
     MedicDeath(MedicDeathEvent),
     Say(SayEvent),
+    PointCaptured(PointCapturedEvent),
+    Flag(FlagEvent),
+    ChargeDeployed(ChargeDeployedEvent),
+    Connected(ConnectedEvent),
+    Disconnected(DisconnectedEvent),
+    // The player's SteamID was checked with Steam.
+    Validated(ValidatedEvent),
+    // Done loading in, after connecting.
+    Entered(EnteredEvent),
+    StageWin(StageWinEvent),
     RoundStart,
+    RoundWin(Team),
+    RoundOvertime,
     GameOver,
+    // Tournament mode starting or mp_tournament_restart, after which the match starts over.
+    TournamentRestart,
+}
+
+impl EventType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventType::Damage(_) => "damage",
+            EventType::Heal(_) => "heal",
+            EventType::Fired(_) => "fired",
+            EventType::Hit(_) => "hit",
+            EventType::Kill(_) => "kill",
+            EventType::Assist(_) => "assist",
+            EventType::ChangeClass(_) => "change_class",
+            EventType::Spawn(_) => "spawn",
+            EventType::MedicDeath(_) => "medic_death",
+            EventType::Say(_) => "say",
+            EventType::PointCaptured(_) => "point_captured",
+            EventType::Flag(_) => "flag",
+            EventType::ChargeDeployed(_) => "charge_deployed",
+            EventType::Connected(_) => "connected",
+            EventType::Disconnected(_) => "disconnected",
+            EventType::Validated(_) => "validated",
+            EventType::Entered(_) => "entered",
+            EventType::StageWin(_) => "stage_win",
+            EventType::RoundStart => "round_start",
+            EventType::RoundWin(_) => "round_win",
+            EventType::RoundOvertime => "round_overtime",
+            EventType::TournamentRestart => "tournament_restart",
+            EventType::GameOver => "game_over",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct FilteredEvents {
     pub player: Player,
     pub events: Vec<Event>,
+    // When the player wasn't on the server during the match, from `absences`.
+    pub absences: Vec<(i64, i64)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Round {
+    pub start: i64,
+    pub end: i64,
+    pub winner: Option<Team>,
+    // When overtime started, if it did.
+    pub overtime: Option<i64>,
 }
 
-pub fn read_log(lines: &Vec<String>) -> (Vec<Player>, Vec<Event>) {
-    let mut events = vec![];
-    let mut players = vec![];
+// About the server and the log itself, from the lines that aren't events.
+#[derive(Debug, Clone, Default)]
+pub struct LogInfo {
+    // When the log was started.
+    pub started: Option<i64>,
+    pub file: Option<String>,
+    pub game: Option<String>,
+    pub version: Option<String>,
+    pub map: Option<String>,
+    // The last value of each server_cvar.
+    pub cvars: BTreeMap<String, String>,
+}
 
-    let before = std::time::Instant::now();
+impl LogInfo {
+    // Takes what it can from a line that isn't an event, returning whether it did.
+    pub fn update(&mut self, line: &str) -> bool {
+        let (timestamp, l) = match (line.get(2..23).and_then(parse_timestamp), line.get(25..)) {
+            (Some(timestamp), Some(l)) => (timestamp, l),
+            _ => return false,
+        };
+
+        // Log file started (file "logs/L0819011.log") (game "/root/steamcmd/tf") (version "6623512")
+        if let Some(rest) = l.strip_prefix("Log file started") {
+            self.started.get_or_insert(timestamp);
+            for (key, value) in properties(rest) {
+                match key {
+                    "file" => self.file = Some(value.to_owned()),
+                    "game" => self.game = Some(value.to_owned()),
+                    "version" => self.version = Some(value.to_owned()),
+                    _ => (),
+                }
+            }
+            return true;
+        }
 
-    for line in lines {
-        let dt = &line[2..23];
-        let timestamp = Utc
-            .datetime_from_str(dt, "%m/%d/%Y - %H:%M:%S").unwrap()
-            .timestamp();
+        // server_cvar: "sm_nextmap" "pl_thundermountain"
+        if let Some(rest) = l.strip_prefix("server_cvar: \"") {
+            if let Some((name, value)) = rest.split_once("\" \"") {
+                self.cvars.insert(name.to_owned(), value.trim_end_matches('"').to_owned());
+                return true;
+            }
+            return false;
+        }
 
-        let l = &line[25..];
-        if let Some(event) = get_event_damaged(l, timestamp, &mut players) {
-            events.push(event);
-        } else if let Some(event) = get_event_healed(l, timestamp, &mut players) {
-            events.push(event);
-        } else if let Some(event) = get_event_fired(l, timestamp, &mut players) {
-            events.push(event);
-        } else if let Some(event) = get_event_hit(l, timestamp, &mut players) {
-            events.push(event);
-        } else if let Some(event) = get_event_killed(l, timestamp, &mut players) {
-            events.push(event);
-        } else if let Some(event) = get_event_changeclass(l, timestamp, &mut players) {
-            events.push(event);
-        } else if let Some(event) = get_event_medicdeath(l, timestamp, &mut players) {
-            events.push(event);
-        } else if let Some(event) = get_event_say(l, timestamp, &mut players) {
-            events.push(event);
-        } else if let Some(event) = get_event_round_start(l, timestamp, &mut players) {
-            events.push(event);
-        } else if let Some(event) = get_event_game_over(l, timestamp, &mut players) {
-            events.push(event);
+        // Loading map "cp_process_f12"
+        // Started map "cp_process_f12" (CRC "...")
+        if let Some(rest) = l.strip_prefix("Loading map \"").or_else(|| l.strip_prefix("Started map \"")) {
+            if let Some((map, _)) = rest.split_once('"') {
+                self.map = Some(map.to_owned());
+                return true;
+            }
         }
+
+        false
     }
 
-    println!("Processed log in {:.2?}", before.elapsed());
+    // e.g. "cp_process_f12, 2021-08-19 12:10, mp_timelimit 30, mp_winlimit 5".
+    pub fn describe(&self) -> String {
+        let mut parts: Vec<String> = vec![];
+        parts.extend(self.map.clone());
+        if let Some(started) = self.started.and_then(|t| chrono::DateTime::from_timestamp(t, 0)) {
+            parts.push(started.format("%Y-%m-%d %H:%M").to_string());
+        }
+        for cvar in ["mp_timelimit", "mp_winlimit", "mp_maxrounds"] {
+            if let Some(value) = self.cvars.get(cvar).filter(|v| *v != "0") {
+                parts.push(format!("{} {}", cvar, value));
+            }
+        }
+        parts.join(", ")
+    }
+}
 
-    (players, events)
+// Parses a log a line at a time, reading every line into the same buffer so memory use doesn't grow with the log.
+pub struct LogReader<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    pub players: Vec<Player>,
+    pub info: LogInfo,
+    pub lines: usize,
 }
 
-pub fn filter_events(players: &Vec<Player>, events: &Vec<Event>) -> Vec<FilteredEvents> {
-    let mut filtered = vec![];
+impl<R: BufRead> LogReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: vec![],
+            players: vec![],
+            info: LogInfo::default(),
+            lines: 0,
+        }
+    }
+}
 
-    // Progress to the start of the match.
-    let from_match_start = events.iter().skip_while(|e| {
-        match e.event {
-            EventType::RoundStart => false,
-            _ => true,
+impl<R: BufRead> Iterator for LogReader<R> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buffer.clear();
+            match self.reader.read_until(b'\n', &mut self.buffer) {
+                Ok(0) => return None,
+                Ok(_) => self.lines += 1,
+                Err(e) => return Some(Err(e)),
+            }
+
+            // Servers don't always write valid UTF-8 (e.g. truncated names), so replace what isn't rather than failing.
+            let line = String::from_utf8_lossy(&self.buffer);
+            let line = line.trim_end();
+            match parse_line(line, &mut self.players) {
+                Some(event) => return Some(Ok(event)),
+                None => {
+                    self.info.update(line);
+                }
+            }
         }
-    });
+    }
+}
+
+// Calls `on_event` with each event as it's parsed, along with the players seen so far. Returns every player, the LogInfo and the number of lines read.
+pub fn read_log<R: BufRead>(reader: R, mut on_event: impl FnMut(Event, &[Player])) -> io::Result<(Vec<Player>, LogInfo, usize)> {
+    let mut log = LogReader::new(reader);
+    while let Some(event) = log.next() {
+        on_event(event?, &log.players);
+    }
+
+    Ok((log.players, log.info, log.lines))
+}
+
+pub fn filter_events(players: &[Player], events: &[Event]) -> Result<Vec<FilteredEvents>, Error> {
+    let mut filtered = vec![];
 
     for player in players {
         //println!("Filtering for events for player: {}", player.name);
 
-        let mut player_events = vec![];
-
         let player_id = players.iter().position(|i| i.id == player.id).unwrap();
+        let player_events = filter_events_for(&[player_id], events)?;
 
-        let mut ev = from_match_start.clone().peekable();
-        player_events.push(ev.next().unwrap().clone());
-        while let Some(event) = ev.next() {
-            let should_push = match &event.event {
-                EventType::Damage(dmg) => if dmg.attacker == player_id || dmg.victim == player_id { true } else { false }
-                EventType::Fired(fire) => {
-                    if fire.player != player_id { 
-                        false
-                    } else if let Some(next) = ev.peek() {
-                        match &next.event {
-                            EventType::Hit(hit) => {
-                                if hit.player == player_id {
-                                    false
-                                } else {
-                                    true
-                                }
-                            },
-                            _ => false
-                        }
-                    } else {
-                        true
-                    }
-                }
-                EventType::Hit(hit) => if hit.player == player_id { true } else { false }
-                EventType::Kill(kill) => if kill.attacker == player_id || kill.victim == player_id { true } else { false }
-                EventType::MedicDeath(md) => if md.attacker == player_id { true } else { false }
-                EventType::Heal(heal) => if heal.healer == player_id || heal.target == player_id { true } else { false }
-                EventType::GameOver => break,
-                _ => false,
-            };
+        let absences = absences(player_id, events);
+        filtered.push(FilteredEvents { player: player.clone(), events: player_events, absences });
+    }
 
-            if should_push {
-                player_events.push(event.clone());
+    Ok(filtered)
+}
+
+// The times the player was off the server during the match: from a disconnect until they're back in the game, or
+// until the end if they never came back, and from the start for anyone that only joined part way through.
+pub fn absences(player_id: usize, events: &[Event]) -> Vec<(i64, i64)> {
+    let (Some(first), Some(last)) = (events.first(), events.last()) else { return vec![] };
+
+    let mut absences = vec![];
+    let mut away_since = None;
+    let mut seen = false;
+    for event in events.iter().filter(|e| e.players().contains(&player_id)) {
+        let since = match &event.event {
+            EventType::Disconnected(_) => {
+                away_since = away_since.or(Some(event.timestamp));
+                None
+            }
+            // Still loading in.
+            EventType::Connected(_) | EventType::Validated(_) => {
+                if !seen {
+                    away_since = Some(first.timestamp);
+                }
+                None
             }
+            EventType::Entered(_) if !seen => Some(first.timestamp),
+            // Anything else they took part in means they're back, even if the log missed them entering the game.
+            _ => away_since.take(),
+        };
+        if let Some(since) = since.filter(|since| *since < event.timestamp) {
+            absences.push((since, event.timestamp));
         }
-
-        filtered.push(FilteredEvents { player: player.clone(), events: player_events });
+        seen = true;
+    }
+    if let Some(since) = away_since.filter(|since| *since < last.timestamp) {
+        absences.push((since, last.timestamp));
     }
 
-    filtered
+    absences
 }
 
+pub fn team_roster(players: &[Player], team: Team) -> Vec<usize> {
+    players.iter().enumerate().filter(|(_, p)| p.team == team).map(|(idx, _)| idx).collect()
+}
 
+// Every event in the match involving any of the given players, e.g. a team's roster.
+pub fn filter_events_for(player_ids: &[usize], events: &[Event]) -> Result<Vec<Event>, Error> {
+    let mut player_events = vec![];
+    let involved = |id: &usize| player_ids.contains(id);
 
+    // Progress to the start of the match.
+    let mut ev = events.iter().skip_while(|e| !matches!(e.event, EventType::RoundStart)).peekable();
+    let start = ev.next().ok_or_else(|| Error::input("the log", "no rounds were played"))?;
+    player_events.push(start.clone());
+    while let Some(event) = ev.next() {
+        let should_push = match &event.event {
+            EventType::Damage(dmg) => involved(&dmg.attacker) || involved(&dmg.victim),
+            EventType::Fired(fire) => {
+                if !involved(&fire.player) {
+                    false
+                } else if let Some(next) = ev.peek() {
+                    match &next.event {
+                        EventType::Hit(hit) => !involved(&hit.player),
+                        _ => false
+                    }
+                } else {
+                    true
+                }
+            }
+            EventType::Hit(hit) => involved(&hit.player),
+            EventType::Kill(kill) => involved(&kill.attacker) || involved(&kill.victim),
+            EventType::MedicDeath(md) => involved(&md.attacker),
+            EventType::Heal(heal) => involved(&heal.healer) || involved(&heal.target),
+            EventType::GameOver => {
+                player_events.push(event.clone());
+                break;
+            }
+            _ => false,
+        };
 
-pub fn get_event_damaged(trimmed_line: &str, timestamp: i64, players: &mut Vec<Player>) -> Option<Event> {
-    let (end, captures) = match DAMAGED.captures(trimmed_line) {
-        Some(c) => (c.get(0).unwrap().end(), c),    // Unwrap is safe because Some(c) implies at least one match.
-        None => return None,
-    };
+        if should_push {
+            player_events.push(event.clone());
+        }
+    }
+
+    Ok(player_events)
+}
+
+// Splits the match into rounds, from each "Round_Start" to its "Round_Win" (or the next "Round_Start"/"Game_Over").
+pub fn find_rounds(events: &[Event]) -> Vec<Round> {
+    let mut rounds: Vec<Round> = vec![];
+    let mut current: Option<Round> = None;
+
+    for event in events {
+        match &event.event {
+            EventType::RoundStart => {
+                // Some servers fire "Round_Start" twice in the same second.
+                if current.as_ref().map(|r| r.start) == Some(event.timestamp) {
+                    continue;
+                }
 
-    let mut realdamage = 0;
-    let mut damage = 0;
-    let mut weapon = "undefined".to_owned();
-    let mut headshot = false;
-    let mut airshot = false;
-    for cap in PROPERTIES.captures_iter(&trimmed_line[end..]) {
-        match &cap[1] {
-            "realdamage" => realdamage = cap[2].parse::<u32>().unwrap(),
-            "damage" => damage = cap[2].parse::<u32>().unwrap(),
-            "weapon" => weapon = cap[2].to_string(),
-            "headshot" => headshot = &cap[2] == "1",
-            "airshot" => airshot = &cap[2] == "1",
+                if let Some(round) = current.take() {
+                    rounds.push(Round { end: event.timestamp, ..round });
+                }
+                current = Some(Round { start: event.timestamp, end: event.timestamp, winner: None, overtime: None });
+            }
+            EventType::RoundOvertime => {
+                if let Some(round) = current.as_mut() {
+                    round.overtime = Some(event.timestamp);
+                }
+            }
+            EventType::RoundWin(team) => {
+                if let Some(round) = current.take() {
+                    rounds.push(Round { end: event.timestamp, winner: Some(*team), ..round });
+                }
+            }
+            EventType::GameOver => {
+                if let Some(round) = current.take() {
+                    rounds.push(Round { end: event.timestamp, ..round });
+                }
+                break;
+            }
             _ => (),
         }
     }
 
-    let attacker_slice = captures.name("attacker").unwrap().as_str();
-    let attacker = get_or_insert_player_pos(attacker_slice, players);
-    let victim_slice = captures.name("victim").unwrap().as_str();
-    let victim = get_or_insert_player_pos(victim_slice, players);
+    if let (Some(round), Some(last)) = (current, events.last()) {
+        rounds.push(Round { end: last.timestamp, ..round });
+    }
+
+    rounds
+}
 
-    let damage = if realdamage > 0 { realdamage } else { damage };
+// Longer than this without anything happening and the match is assumed to be over, e.g. the server was left empty.
+const MATCH_GAP: i64 = 5 * 60;
 
-    Some(Event::new(
-        timestamp,
-        EventType::Damage(DamageEvent {attacker, victim, damage, weapon, headshot, airshot }),
-    ))
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchEnd {
+    GameOver,
+    Restart,
+    Gap,
+    // The log stopped before the match was over.
+    LogEnd,
 }
 
-pub fn get_event_healed(trimmed_line: &str, timestamp: i64, players: &mut Vec<Player>) -> Option<Event> {
-    let (end, captures) = match HEALED.captures(trimmed_line) {
-        Some(c) => (c.get(0).unwrap().end(), c),
-        None => return None,
-    };
+// One match in a log that might have several, e.g. a pregame before a tournament restart or two back to back matches.
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub start: i64,
+    pub end: i64,
+    pub rounds: usize,
+    pub ended: MatchEnd,
+    // Indices of its events, from its first "Round_Start".
+    pub events: Range<usize>,
+}
 
-    let mut healing = 0;
-    for cap in PROPERTIES.captures_iter(&trimmed_line[end..]) {
-        match &cap[1] {
-            "healing" => healing = cap[2].parse::<u32>().unwrap(),
+// Splits the log into matches, each from a "Round_Start" up to a "Game_Over", a tournament restart or a long gap.
+pub fn find_matches(events: &[Event]) -> Vec<Match> {
+    let mut splitter = MatchSplitter::new(None);
+    for event in events {
+        splitter.push(event.clone());
+    }
+    splitter.finish().0
+}
+
+// Splits events into matches as they're read, like find_matches, but only holds on to the events of the match in
+// progress and the one that'll be used: `selected`, or the first that was played to the end (the latest if none have
+// been yet). So a log with many matches is never in memory all at once.
+#[derive(Debug, Clone, Default)]
+pub struct MatchSplitter {
+    selected: Option<usize>,
+    matches: Vec<Match>,
+    // How many events have been pushed, for the matches' indices.
+    pub count: usize,
+    // The match in progress, from its "Round_Start", and the index of that.
+    current: Vec<Event>,
+    start: usize,
+    last_timestamp: i64,
+    // The match that'll be used, by its index in `matches`, and its events.
+    kept: Option<(usize, Vec<Event>)>,
+}
+
+impl MatchSplitter {
+    pub fn new(selected: Option<usize>) -> Self {
+        Self { selected, ..Self::default() }
+    }
+
+    pub fn push(&mut self, event: Event) {
+        let idx = self.count;
+        self.count += 1;
+        if !self.current.is_empty() && event.timestamp - self.last_timestamp > MATCH_GAP {
+            self.end(MatchEnd::Gap);
+        }
+        self.last_timestamp = event.timestamp;
+
+        match event.event {
+            EventType::RoundStart if self.current.is_empty() => {
+                self.start = idx;
+                self.current.push(event);
+            }
+            EventType::TournamentRestart => self.end(MatchEnd::Restart),
+            EventType::GameOver if !self.current.is_empty() => {
+                self.current.push(event);
+                self.end(MatchEnd::GameOver);
+            }
+            _ if !self.current.is_empty() => self.current.push(event),
             _ => (),
         }
     }
 
-    let healer_slice = captures.name("player").unwrap().as_str();
-    let healer = get_or_insert_player_pos(healer_slice, players);
-    let target_slice = captures.name("target").unwrap().as_str();
-    let target = get_or_insert_player_pos(target_slice, players);
+    // Ends the match in progress, returning every match and the one that'll be used, by its index, with its events.
+    pub fn finish(mut self) -> (Vec<Match>, Option<(usize, Vec<Event>)>) {
+        self.end(MatchEnd::LogEnd);
+        (self.matches, self.kept)
+    }
 
-    Some(Event::new(
-        timestamp,
-        EventType::Heal(HealEvent { healer, target, healing }),
-    ))
+    fn end(&mut self, ended: MatchEnd) {
+        if self.current.is_empty() {
+            return;
+        }
+        let events = std::mem::take(&mut self.current);
+        let number = self.matches.len();
+        self.matches.push(Match {
+            start: events[0].timestamp,
+            end: events[events.len() - 1].timestamp,
+            rounds: find_rounds(&events).len(),
+            ended,
+            events: self.start..self.start + events.len(),
+        });
+
+        let keep = match (self.selected, &self.kept) {
+            (Some(selected), _) => number == selected,
+            (None, Some((kept, _))) => self.matches[*kept].ended != MatchEnd::GameOver,
+            (None, None) => true,
+        };
+        if keep {
+            self.kept = Some((number, events));
+        }
+    }
 }
 
-pub fn get_event_killed(trimmed_line: &str, timestamp: i64, players: &mut Vec<Player>) -> Option<Event> {
-    let (end, captures) = match KILLED.captures(trimmed_line) {
-        Some(c) => (c.get(0).unwrap().end(), c),
-        None => return None,
-    };
+// A single line of the log, if it's an event we know about.
+//
+// Rather than trying a regex per event type, the subject and verb are split out once and used to pick the event:
+// L 08/19/2021 - 12:10:16: "*subject*" *verb* "*object*" [against "*target*"] (*key* "*value*")...
+pub fn parse_line(line: &str, players: &mut Vec<Player>) -> Option<Event> {
+    let timestamp = parse_timestamp(line.get(2..23)?)?;
+    let l = line.get(25..)?;
+
+    if let Some(rest) = l.strip_prefix("World triggered \"") {
+        return parse_world(rest, timestamp);
+    }
 
-    let mut headshot = false;
-    let mut backstab = false;
-    for cap in PROPERTIES.captures_iter(&trimmed_line[end..]) {
-        match &cap[1] {
-            "customkill" => {
-                match &cap[2] {
+    // Tournament mode started
+    // rcon from "10.0.0.5:51234": command "mp_tournament_restart"
+    if l.starts_with("Tournament mode started") || (l.starts_with("rcon from \"") && l.ends_with("command \"mp_tournament_restart\"")) {
+        return Some(Event::new(timestamp, EventType::TournamentRestart));
+    }
+
+    // Team "Blue" triggered "pointcaptured" (cp "0") (cpname "#koth_viaduct_cap") (numcappers "1") (player1 "*Sexier Turtle<9><[U:1:242326504]><Blue>*") (position1 "-1512 12 231")
+    if let Some(rest) = l.strip_prefix("Team \"") {
+        let (team, rest) = rest.split_once("\" triggered \"pointcaptured\"")?;
+        if !matches!(team, "Red" | "Blue") {
+            return None;
+        }
+
+        let mut cp = 0;
+        let mut name = "undefined".to_owned();
+        let mut cappers = vec![];
+        for (key, value) in properties(rest) {
+            match key {
+                "cp" => cp = value.parse::<u32>().ok()?,
+                "cpname" => name = value.to_owned(),
+                key if key.starts_with("player") => cappers.extend(get_or_insert_player_pos(value, players)),
+                _ => (),
+            }
+        }
+
+        let team = get_team(team);
+        return Some(Event::new(timestamp, EventType::PointCaptured(PointCapturedEvent { team, cp, name, cappers })));
+    }
+
+    let (subject, rest) = player_token(l.strip_prefix('"')?)?;
+
+    if let Some(rest) = rest.strip_prefix("triggered \"") {
+        let (action, rest) = rest.split_once('"')?;
+        // Only some actions have someone on the receiving end.
+        let (target, rest) = match rest.strip_prefix(" against \"") {
+            Some(rest) => {
+                let (target, rest) = player_token(rest)?;
+                (Some(target), rest)
+            }
+            None => (None, rest),
+        };
+
+        return parse_triggered(action, subject, target, rest, timestamp, players);
+    }
+
+    // "*ozy<20><[U:1:71235035]><Red>*" killed "*lizrd-wizrd<10><[U:1:320890290]><Blue>*" with "*quake_rl*" (attacker_position "-2481 725 201") (victim_position "-2537 832 128")
+    if let Some(rest) = rest.strip_prefix("killed \"") {
+        let (victim, rest) = player_token(rest)?;
+        let (weapon, rest) = rest.strip_prefix("with \"")?.split_once('"')?;
+
+        let mut headshot = false;
+        let mut backstab = false;
+        for (key, value) in properties(rest) {
+            if key == "customkill" {
+                match value {
                     "headshot" => headshot = true,
                     "backstab" => backstab = true,
                     _ => (),
                 }
-                //headshot = &cap[2] == "1",
             }
-            //"backstab" => backstab = &cap[2] == "1",
-            _ => (),
         }
+
+        let weapon = weapon.to_owned();
+        let attacker = get_or_insert_player_pos(subject, players)?;
+        let victim = get_or_insert_player_pos(victim, players)?;
+
+        return Some(Event::new(timestamp, EventType::Kill(KillEvent { attacker, victim, weapon, headshot, backstab })));
     }
 
-    let weapon = captures.name("weapon").unwrap().as_str().to_owned();
-    let attacker_slice = captures.name("attacker").unwrap().as_str();
-    let attacker = get_or_insert_player_pos(attacker_slice, players);
-    let victim_slice = captures.name("victim").unwrap().as_str();
-    let victim = get_or_insert_player_pos(victim_slice, players);
+    // "*calski<26><[U:1:98109542]><>*" connected, address "*119.18.3.219:31822*"
+    if let Some(rest) = rest.strip_prefix("connected, address \"") {
+        let address = rest.split_once('"')?.0.to_owned();
+        let player = get_or_insert_player_pos(subject, players)?;
+        return Some(Event::new(timestamp, EventType::Connected(ConnectedEvent { player, address })));
+    }
 
-    Some(Event::new(
-        timestamp,
-        EventType::Kill(KillEvent { attacker, victim, weapon, headshot, backstab }),
-    ))
-}
+    // "*calski<24><[U:1:98109542]><Blue>*" disconnected (reason "*Disconnect by user.*")
+    if let Some(rest) = rest.strip_prefix("disconnected") {
+        let reason = properties(rest).find(|(key, _)| *key == "reason").map_or("undefined", |(_, reason)| reason).to_owned();
+        let player = get_or_insert_player_pos(subject, players)?;
+        return Some(Event::new(timestamp, EventType::Disconnected(DisconnectedEvent { player, reason })));
+    }
 
-pub fn get_event_fired(trimmed_line: &str, timestamp: i64, players: &mut Vec<Player>) -> Option<Event> {
-    let (end, captures) = match FIRED.captures(trimmed_line) {
-        Some(c) => (c.get(0).unwrap().end(), c),
-        None => return None,
-    };
+    // "*calski<26><[U:1:98109542]><>*" STEAM USERID validated
+    if rest == "STEAM USERID validated" {
+        let player = get_or_insert_player_pos(subject, players)?;
+        return Some(Event::new(timestamp, EventType::Validated(ValidatedEvent { player })));
+    }
 
-    let mut weapon = "undefined".to_owned();
-    for cap in PROPERTIES.captures_iter(&trimmed_line[end..]) {
-        match &cap[1] {
-            "weapon" => weapon = cap[2].to_string(),
-            _ => (),
-        }
+    // "*calski<26><[U:1:98109542]><>*" entered the game
+    if rest == "entered the game" {
+        let player = get_or_insert_player_pos(subject, players)?;
+        return Some(Event::new(timestamp, EventType::Entered(EnteredEvent { player })));
     }
 
-    let player_slice = captures.name("player").unwrap().as_str();
-    let player = get_or_insert_player_pos(player_slice, players);
+    // "*oh no<4><[U:1:83248160]><Red>*" changed role to "*heavyweapons*"
+    if let Some(rest) = rest.strip_prefix("changed role to \"") {
+        let class = get_class(rest.split_once('"')?.0);
+        let player = get_or_insert_player_pos(subject, players)?;
+        return Some(Event::new(timestamp, EventType::ChangeClass(ChangeClassEvent { player, class })));
+    }
+
+    // "*oh no<4><[U:1:83248160]><Red>*" spawned as "*Heavyweapons*"
+    if let Some(rest) = rest.strip_prefix("spawned as \"") {
+        let class = get_class(rest.split_once('"')?.0);
+        let player = get_or_insert_player_pos(subject, players)?;
+        return Some(Event::new(timestamp, EventType::Spawn(SpawnEvent { player, class })));
+    }
+
+    // "*tal<11><[U:1:91618645]><Red>*" say "*gg*"
+    // "*tal<11><[U:1:91618645]><Red>*" say_team "*uber in 10*"
+    let said = match rest.strip_prefix("say_team \"") {
+        Some(rest) => Some((rest, true)),
+        None => rest.strip_prefix("say \"").map(|rest| (rest, false)),
+    };
+    if let Some((rest, team_only)) = said {
+        let text = rest.strip_suffix('"')?;
+        if text.is_empty() {
+            return None;
+        }
 
-    Some(Event::new(
-        timestamp,
-        EventType::Fired(FiredEvent { player, weapon }),
-    ))
+        let player = get_or_insert_player_pos(subject, players)?;
+        return Some(Event::new(timestamp, EventType::Say(SayEvent { player, text: text.to_owned(), team_only })));
+    }
+
+    None
 }
 
-pub fn get_event_hit(trimmed_line: &str, timestamp: i64, players: &mut Vec<Player>) -> Option<Event> {
-    let (end, captures) = match HIT.captures(trimmed_line) {
-        Some(c) => (c.get(0).unwrap().end(), c),
-        None => return None,
+// "*Sexy Turtle<17><[U:1:296600241]><Red>*" triggered "*damage*" against "*calski<26><[U:1:98109542]><Blue>*" (damage "13") (weapon "shotgun_primary")
+fn parse_triggered(action: &str, subject: &str, target: Option<&str>, rest: &str, timestamp: i64, players: &mut Vec<Player>) -> Option<Event> {
+    let event = match (action, target) {
+        ("damage", Some(victim)) => {
+            let mut realdamage = 0;
+            let mut damage = 0;
+            let mut weapon = "undefined".to_owned();
+            let mut headshot = false;
+            let mut airshot = false;
+            for (key, value) in properties(rest) {
+                match key {
+                    "realdamage" => realdamage = value.parse::<u32>().ok()?,
+                    "damage" => damage = value.parse::<u32>().ok()?,
+                    "weapon" => weapon = value.to_owned(),
+                    "headshot" => headshot = value == "1",
+                    "airshot" => airshot = value == "1",
+                    _ => (),
+                }
+            }
+
+            let attacker = get_or_insert_player_pos(subject, players)?;
+            let victim = get_or_insert_player_pos(victim, players)?;
+            let damage = if realdamage > 0 { realdamage } else { damage };
+
+            EventType::Damage(DamageEvent { attacker, victim, damage, weapon, headshot, airshot })
+        }
+        // "tal<11><[U:1:91618645]><Red>" triggered "healed" against "Flow<14><[U:1:152978378]><Red>" (healing "32")
+        ("healed", Some(target)) => {
+            let mut healing = 0;
+            for (key, value) in properties(rest) {
+                if key == "healing" {
+                    healing = value.parse::<u32>().ok()?;
+                }
+            }
+
+            let healer = get_or_insert_player_pos(subject, players)?;
+            let target = get_or_insert_player_pos(target, players)?;
+
+            EventType::Heal(HealEvent { healer, target, healing })
+        }
+        // "*[VIP] Zach<8><[U:1:250686100]><Blue>*" triggered "medic_death" against "*tal<11><[U:1:91618645]><Red>*" (healing "0") (ubercharge "0")
+        ("medic_death", Some(victim)) => {
+            let mut drop = false;
+            for (key, value) in properties(rest) {
+                if key == "ubercharge" {
+                    drop = value == "1";
+                }
+            }
+
+            let attacker = get_or_insert_player_pos(subject, players)?;
+            let victim = get_or_insert_player_pos(victim, players)?;
+
+            EventType::MedicDeath(MedicDeathEvent { attacker, victim, drop })
+        }
+        // "*BONKITUP123<13><[U:1:105391228]><Red>*" triggered "shot_fired" (weapon "tf_projectile_pipe_remote")
+        ("shot_fired", None) | ("shot_hit", None) => {
+            let mut weapon = "undefined".to_owned();
+            for (key, value) in properties(rest) {
+                if key == "weapon" {
+                    weapon = value.to_owned();
+                }
+            }
+
+            let player = get_or_insert_player_pos(subject, players)?;
+            if action == "shot_fired" {
+                EventType::Fired(FiredEvent { player, weapon })
+            } else {
+                EventType::Hit(HitEvent { player, weapon })
+            }
+        }
+        // "*tal<11><[U:1:91618645]><Red>*" triggered "kill assist" against "*Flow<14><[U:1:152978378]><Blue>*" (assister_position "-1011 -2030 -447") ...
+        ("kill assist", Some(victim)) => {
+            let assister = get_or_insert_player_pos(subject, players)?;
+            let victim = get_or_insert_player_pos(victim, players)?;
+
+            EventType::Assist(AssistEvent { assister, victim })
+        }
+        // "*roseanne park<16><[U:1:171796422]><Blue>*" triggered "chargedeployed" (medigun "medigun")
+        ("chargedeployed", None) => {
+            let mut medigun = "undefined".to_owned();
+            for (key, value) in properties(rest) {
+                if key == "medigun" {
+                    medigun = value.to_owned();
+                }
+            }
+
+            let player = get_or_insert_player_pos(subject, players)?;
+
+            EventType::ChargeDeployed(ChargeDeployedEvent { player, medigun })
+        }
+        // "*Flow<14><[U:1:152978378]><Blue>*" triggered "flagevent" (event "picked up") (position "-1034 2412 -63")
+        ("flagevent", None) => {
+            let (_, action) = properties(rest).find(|(key, _)| *key == "event")?;
+            let action = match action {
+                "picked up" => FlagAction::PickedUp,
+                "dropped" => FlagAction::Dropped,
+                "captured" => FlagAction::Captured,
+                "defended" => FlagAction::Defended,
+                _ => return None,
+            };
+
+            let player = get_or_insert_player_pos(subject, players)?;
+
+            EventType::Flag(FlagEvent { player, action })
+        }
+        _ => return None,
     };
 
-    let mut weapon = "undefined".to_owned();
-    for cap in PROPERTIES.captures_iter(&trimmed_line[end..]) {
-        match &cap[1] {
-            "weapon" => weapon = cap[2].to_string(),
-            _ => (),
+    Some(Event::new(timestamp, event))
+}
+
+// World triggered "*Round_Win*" (winner "Blue")
+fn parse_world(rest: &str, timestamp: i64) -> Option<Event> {
+    let (action, rest) = rest.split_once('"')?;
+    let event = match action {
+        "Round_Start" => EventType::RoundStart,
+        "Round_Win" => {
+            let (_, winner) = properties(rest).find(|(key, _)| *key == "winner")?;
+            if !matches!(winner, "Red" | "Blue") {
+                return None;
+            }
+            EventType::RoundWin(get_team(winner))
         }
+        "Round_Overtime" => EventType::RoundOvertime,
+        // World triggered "Mini_Round_Win" (winner "Blue") (round "round_a")
+        "Mini_Round_Win" => {
+            let mut team = None;
+            let mut stage = "undefined".to_owned();
+            for (key, value) in properties(rest) {
+                match key {
+                    "winner" if matches!(value, "Red" | "Blue") => team = Some(get_team(value)),
+                    "round" => stage = value.to_owned(),
+                    _ => (),
+                }
+            }
+            EventType::StageWin(StageWinEvent { team: team?, stage })
+        }
+        "Game_Over" => EventType::GameOver,
+        _ => return None,
+    };
+
+    Some(Event::new(timestamp, event))
+}
+
+// "08/19/2021 - 12:10:16", parsed by hand as it's done for every line.
+fn parse_timestamp(dt: &str) -> Option<i64> {
+    let number = |range: std::ops::Range<usize>| dt.get(range)?.parse::<u32>().ok();
+    let date = NaiveDate::from_ymd_opt(number(6..10)? as i32, number(0..2)?, number(3..5)?)?;
+    let time = date.and_hms_opt(number(13..15)?, number(16..18)?, number(19..21)?)?;
+    Some(time.and_utc().timestamp())
+}
+
+// Splits `name<uid><steamid><team>" rest` (just after the opening quote) into the player and the rest of the line.
+// Names can contain `>"` themselves, so it's the first one that closes a team.
+fn player_token(s: &str) -> Option<(&str, &str)> {
+    let end = s.match_indices(">\"").map(|(idx, _)| idx + 1).find(|&end| closes_team(&s[..end]))?;
+    Some((&s[..end], s[end + 1..].trim_start()))
+}
+
+fn closes_team(s: &str) -> bool {
+    match s.strip_suffix('>').and_then(|s| s.rsplit_once('<')) {
+        Some((_, team)) => matches!(team, "Red" | "Blue" | "Spectator" | "Unassigned" | "Console" | "unknown" | ""),
+        None => false,
     }
+}
 
-    let player_slice = captures.name("player").unwrap().as_str();
-    let player = get_or_insert_player_pos(player_slice, players);
+// The `(key "value")` pairs at the end of a line.
+fn properties(s: &str) -> Properties<'_> {
+    Properties { rest: s }
+}
 
-    Some(Event::new(
-        timestamp,
-        EventType::Hit(HitEvent { player, weapon }),
-    ))
+struct Properties<'a> {
+    rest: &'a str,
 }
 
-pub fn get_event_changeclass(trimmed_line: &str, timestamp: i64, players: &mut Vec<Player>) -> Option<Event> {
-    let captures = match CHANGEDCLASS.captures(trimmed_line) {
-        Some(c) => c,
-        None => return None,
-    };
+impl<'a> Iterator for Properties<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.rest.find('(')? + 1;
+            self.rest = &self.rest[start..];
 
-    let role = captures.name("role").unwrap().as_str();
-    let class = match role {
+            let (key, rest) = match self.rest.split_once(" \"") {
+                Some(split) => split,
+                None => continue,
+            };
+            if key.is_empty() || !key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+                continue;
+            }
+
+            let (value, rest) = match rest.split_once("\")") {
+                Some(split) => split,
+                None => continue,
+            };
+            self.rest = rest;
+            return Some((key, value));
+        }
+    }
+}
+
+// "changed role to" uses lowercase names, "spawned as" capitalises them.
+fn get_class(role: &str) -> Class {
+    match role.to_ascii_lowercase().as_str() {
         "scout" => Class::Scout,
         "soldier" => Class::Soldier,
         "pyro" => Class::Pyro,
@@ -386,76 +993,490 @@ pub fn get_event_changeclass(trimmed_line: &str, timestamp: i64, players: &mut V
         "sniper" => Class::Sniper,
         "spy" => Class::Spy,
         _ => Class::Unknown,
-    };
+    }
+}
 
-    let player_slice = captures.name("player").unwrap().as_str();
-    let player = get_or_insert_player_pos(player_slice, players);
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufReader;
 
-    Some(Event::new(
-        timestamp,
-        EventType::ChangeClass(ChangeClassEvent { player, class }),
-    ))
-}
+    use super::*;
 
-pub fn get_event_medicdeath(trimmed_line: &str, timestamp: i64, players: &mut Vec<Player>) -> Option<Event> {
-    let (end, captures) = match MEDICDIED.captures(trimmed_line) {
-        Some(c) => (c.get(0).unwrap().end(), c),
-        None => return None,
-    };
+    const RED: &str = "tal<11><[U:1:91618645]><Red>";
+    const BLU: &str = "Flow<14><[U:1:152978378]><Blue>";
 
-    let mut drop = false;
-    for cap in PROPERTIES.captures_iter(&trimmed_line[end..]) {
-        match &cap[1] {
-            "ubercharge" => drop = &cap[2] == "1",
-            _ => (),
+    fn parse(rest: &str) -> (Option<Event>, Vec<Player>) {
+        let mut players = vec![];
+        let event = parse_line(&format!("L 08/19/2021 - 12:10:16: {}", rest), &mut players);
+        (event, players)
+    }
+
+    fn event(rest: &str) -> EventType {
+        let (event, _) = parse(rest);
+        let event = event.unwrap_or_else(|| panic!("not parsed: {}", rest));
+        assert_eq!(event.timestamp, 1629375016);
+        event.event
+    }
+
+    #[test]
+    fn timestamp() {
+        assert_eq!(parse_timestamp("08/19/2021 - 12:10:16"), Some(1629375016));
+        assert_eq!(parse_timestamp("13/19/2021 - 12:10:16"), None);
+        assert_eq!(parse_timestamp("08/19/2021 - 12:10"), None);
+    }
+
+    #[test]
+    fn player_tokens() {
+        assert_eq!(player_token(&format!("{}\" killed", RED)), Some((RED, "killed")));
+        assert_eq!(player_token("calski<26><[U:1:98109542]><>\" connected"), Some(("calski<26><[U:1:98109542]><>", "connected")));
+        assert_eq!(player_token("a>\"b<5><[U:1:5]><Red>\" say"), Some(("a>\"b<5><[U:1:5]><Red>", "say")));
+        assert_eq!(player_token("no player here"), None);
+    }
+
+    #[test]
+    fn property_pairs() {
+        let pairs: Vec<_> = properties(" (damage \"13\") (weapon \"shotgun_primary\")").collect();
+        assert_eq!(pairs, [("damage", "13"), ("weapon", "shotgun_primary")]);
+
+        // Anything that isn't a `(key "value")` pair is skipped.
+        let pairs: Vec<_> = properties(" (junk) (bad key \"1\") (healing \"32\") (open \"").collect();
+        assert_eq!(pairs, [("healing", "32")]);
+        assert_eq!(properties("").count(), 0);
+    }
+
+    #[test]
+    fn damage() {
+        match event(&format!("\"{}\" triggered \"damage\" against \"{}\" (damage \"120\") (realdamage \"95\") (weapon \"quake_rl\") (airshot \"1\")", RED, BLU)) {
+            EventType::Damage(d) => {
+                assert_eq!((d.attacker, d.victim, d.damage), (0, 1, 95));
+                assert_eq!(d.weapon, "quake_rl");
+                assert!(d.airshot && !d.headshot);
+            }
+            e => panic!("{:?}", e),
         }
+
+        // A missing weapon, and properties we don't know about.
+        match event(&format!("\"{}\" triggered \"damage\" against \"{}\" (damage \"13\") (crit \"mini\")", RED, BLU)) {
+            EventType::Damage(d) => {
+                assert_eq!(d.damage, 13);
+                assert_eq!(d.weapon, "undefined");
+            }
+            e => panic!("{:?}", e),
+        }
+
+        // Damage needs someone to have taken it.
+        assert!(parse(&format!("\"{}\" triggered \"damage\" (damage \"13\")", RED)).0.is_none());
     }
 
-    let attacker_slice = captures.name("attacker").unwrap().as_str();
-    let attacker = get_or_insert_player_pos(attacker_slice, players);
-    let victim_slice = captures.name("victim").unwrap().as_str();
-    let victim = get_or_insert_player_pos(victim_slice, players);
+    #[test]
+    fn heal() {
+        match event(&format!("\"{}\" triggered \"healed\" against \"{}\" (healing \"32\")", RED, BLU)) {
+            EventType::Heal(h) => assert_eq!((h.healer, h.target, h.healing), (0, 1, 32)),
+            e => panic!("{:?}", e),
+        }
+    }
 
-    Some(Event::new(
-        timestamp,
-        EventType::MedicDeath(MedicDeathEvent { attacker, victim, drop }),
-    ))
-}
+    #[test]
+    fn fired_and_hit() {
+        match event(&format!("\"{}\" triggered \"shot_fired\" (weapon \"tf_projectile_pipe_remote\")", RED)) {
+            EventType::Fired(f) => assert_eq!(f.weapon, "tf_projectile_pipe_remote"),
+            e => panic!("{:?}", e),
+        }
+        match event(&format!("\"{}\" triggered \"shot_hit\" (weapon \"scattergun\")", RED)) {
+            EventType::Hit(h) => assert_eq!(h.weapon, "scattergun"),
+            e => panic!("{:?}", e),
+        }
+    }
 
-pub fn get_event_say(trimmed_line: &str, timestamp: i64, players: &mut Vec<Player>) -> Option<Event> {
-    let captures = match SAY.captures(trimmed_line) {
-        Some(c) => c,
-        None => return None,
-    };
+    #[test]
+    fn kill() {
+        let line = format!(
+            "\"{}\" killed \"{}\" with \"sniperrifle\" (customkill \"headshot\") (attacker_position \"-2481 725 201\") (victim_position \"-2537 832 128\")",
+            RED, BLU,
+        );
+        match event(&line) {
+            EventType::Kill(k) => {
+                assert_eq!((k.attacker, k.victim), (0, 1));
+                assert_eq!(k.weapon, "sniperrifle");
+                assert!(k.headshot && !k.backstab);
+            }
+            e => panic!("{:?}", e),
+        }
+    }
 
-    let player_slice = captures.name("player").unwrap().as_str();
-    let player = get_or_insert_player_pos(player_slice, players);
-    let text = captures.name("message").unwrap().as_str().to_owned();
+    #[test]
+    fn assist() {
+        match event(&format!("\"{}\" triggered \"kill assist\" against \"{}\" (assister_position \"-1011 -2030 -447\")", RED, BLU)) {
+            EventType::Assist(a) => assert_eq!((a.assister, a.victim), (0, 1)),
+            e => panic!("{:?}", e),
+        }
+    }
 
-    Some(Event::new(
-        timestamp,
-        EventType::Say(SayEvent { player, text }),
-    ))
-}
+    #[test]
+    fn classes() {
+        match event(&format!("\"{}\" changed role to \"heavyweapons\"", RED)) {
+            EventType::ChangeClass(c) => assert_eq!(c.class, Class::Heavy),
+            e => panic!("{:?}", e),
+        }
+        match event(&format!("\"{}\" spawned as \"Medic\"", RED)) {
+            EventType::Spawn(s) => assert_eq!(s.class, Class::Medic),
+            e => panic!("{:?}", e),
+        }
+        match event(&format!("\"{}\" spawned as \"Civilian\"", RED)) {
+            EventType::Spawn(s) => assert_eq!(s.class, Class::Unknown),
+            e => panic!("{:?}", e),
+        }
+    }
 
-pub fn get_event_round_start(trimmed_line: &str, timestamp: i64, _players: &mut Vec<Player>) -> Option<Event> {
-    if !ROUND_START.is_match(trimmed_line) {
-        return None;
+    #[test]
+    fn medic_death() {
+        match event(&format!("\"{}\" triggered \"medic_death\" against \"{}\" (healing \"0\") (ubercharge \"1\")", BLU, RED)) {
+            EventType::MedicDeath(m) => {
+                assert_eq!((m.attacker, m.victim), (0, 1));
+                assert!(m.drop);
+            }
+            e => panic!("{:?}", e),
+        }
     }
 
-    Some(Event::new(
-        timestamp,
-        EventType::RoundStart,
-    ))
-}
+    #[test]
+    fn say() {
+        match event(&format!("\"{}\" say \"gg\"", RED)) {
+            EventType::Say(s) => {
+                assert_eq!(s.text, "gg");
+                assert!(!s.team_only);
+            }
+            e => panic!("{:?}", e),
+        }
+        match event(&format!("\"{}\" say_team \"uber in \"10\"\"", RED)) {
+            EventType::Say(s) => {
+                assert_eq!(s.text, "uber in \"10\"");
+                assert!(s.team_only);
+            }
+            e => panic!("{:?}", e),
+        }
+        assert!(parse(&format!("\"{}\" say \"\"", RED)).0.is_none());
+    }
+
+    #[test]
+    fn point_captured() {
+        let line = format!(
+            "Team \"Blue\" triggered \"pointcaptured\" (cp \"2\") (cpname \"#koth_viaduct_cap\") (numcappers \"2\") (player1 \"{}\") (position1 \"-1512 12 231\") (player2 \"{}\") (position2 \"-1500 10 231\")",
+            BLU, RED,
+        );
+        let (event, players) = parse(&line);
+        match event.unwrap().event {
+            EventType::PointCaptured(pc) => {
+                assert_eq!(pc.team, Team::Blu);
+                assert_eq!(pc.cp, 2);
+                assert_eq!(pc.name, "#koth_viaduct_cap");
+                assert_eq!(pc.cappers, [0, 1]);
+            }
+            e => panic!("{:?}", e),
+        }
+        assert_eq!(players.len(), 2);
+
+        assert!(parse("Team \"Spectator\" triggered \"pointcaptured\" (cp \"0\")").0.is_none());
+    }
+
+    #[test]
+    fn flag() {
+        match event(&format!("\"{}\" triggered \"flagevent\" (event \"picked up\") (position \"-1034 2412 -63\")", BLU)) {
+            EventType::Flag(f) => assert_eq!(f.action, FlagAction::PickedUp),
+            e => panic!("{:?}", e),
+        }
+        assert!(parse(&format!("\"{}\" triggered \"flagevent\" (position \"-1034 2412 -63\")", BLU)).0.is_none());
+    }
+
+    #[test]
+    fn charge_deployed() {
+        match event(&format!("\"{}\" triggered \"chargedeployed\" (medigun \"kritzkrieg\")", RED)) {
+            EventType::ChargeDeployed(c) => assert_eq!(c.medigun, "kritzkrieg"),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn connections() {
+        let joining = "calski<26><[U:1:98109542]><>";
+        match event(&format!("\"{}\" connected, address \"119.18.3.219:31822\"", joining)) {
+            EventType::Connected(c) => assert_eq!(c.address, "119.18.3.219:31822"),
+            e => panic!("{:?}", e),
+        }
+        assert!(matches!(event(&format!("\"{}\" STEAM USERID validated", joining)), EventType::Validated(_)));
+        assert!(matches!(event(&format!("\"{}\" entered the game", joining)), EventType::Entered(_)));
+        match event(&format!("\"{}\" disconnected (reason \"Disconnect by user.\")", RED)) {
+            EventType::Disconnected(d) => assert_eq!(d.reason, "Disconnect by user."),
+            e => panic!("{:?}", e),
+        }
+        match event(&format!("\"{}\" disconnected", RED)) {
+            EventType::Disconnected(d) => assert_eq!(d.reason, "undefined"),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn unassigned() {
+        let (event, players) = parse("\"a<5><[U:1:5]><Unassigned>\" disconnected (reason \"Disconnect by user.\")");
+        match event.unwrap().event {
+            EventType::Disconnected(d) => assert_eq!(d.reason, "Disconnect by user."),
+            e => panic!("{:?}", e),
+        }
+        assert_eq!(players[0].id, "[U:1:5]");
+        assert_eq!(players[0].team, Team::Spectator);
+
+        // Lines with anything else where the player should be are skipped.
+        let (event, players) = parse("\"<[U:1:5]><Red>\" disconnected (reason \"Disconnect by user.\")");
+        assert!(event.is_none());
+        assert!(players.is_empty());
+        let line = format!("Team \"Red\" triggered \"pointcaptured\" (cp \"0\") (player1 \"{}\") (player2 \"nobody\")", RED);
+        match parse(&line).0.unwrap().event {
+            EventType::PointCaptured(pc) => assert_eq!(pc.cappers, [0]),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn world() {
+        assert!(matches!(event("World triggered \"Round_Start\""), EventType::RoundStart));
+        assert!(matches!(event("World triggered \"Round_Win\" (winner \"Blue\")"), EventType::RoundWin(Team::Blu)));
+        assert!(matches!(event("World triggered \"Round_Overtime\""), EventType::RoundOvertime));
+        assert!(matches!(event("World triggered \"Game_Over\" reason \"Reached Win Limit\""), EventType::GameOver));
+        match event("World triggered \"Mini_Round_Win\" (winner \"Red\") (round \"round_a\")") {
+            EventType::StageWin(s) => {
+                assert_eq!(s.team, Team::Red);
+                assert_eq!(s.stage, "round_a");
+            }
+            e => panic!("{:?}", e),
+        }
+
+        assert!(parse("World triggered \"Round_Win\"").0.is_none());
+        assert!(parse("World triggered \"Round_Setup_Begin\"").0.is_none());
+    }
+
+    #[test]
+    fn tournament_restart() {
+        assert!(matches!(event("Tournament mode started"), EventType::TournamentRestart));
+        assert!(matches!(event("rcon from \"10.0.0.5:51234\": command \"mp_tournament_restart\""), EventType::TournamentRestart));
+    }
+
+    #[test]
+    fn awkward_names() {
+        let name = "<<\"hi\">> (x \"y\")";
+        let line = format!("\"{}<5><[U:1:5]><Red>\" killed \"a>\"b<6><[U:1:6]><Blue>\" with \"scattergun\"", name);
+        let (event, players) = parse(&line);
+        match event.unwrap().event {
+            EventType::Kill(k) => assert_eq!(k.weapon, "scattergun"),
+            e => panic!("{:?}", e),
+        }
+        assert_eq!(players[0].name, name);
+        assert_eq!(players[0].id, "[U:1:5]");
+        assert_eq!(players[1].name, "a>\"b");
+        assert_eq!(players[1].team, Team::Blu);
+    }
+
+    #[test]
+    fn unknown_lines() {
+        assert!(parse("Log file started (file \"logs/L0819011.log\") (game \"/home/tf2/tf\") (version \"6630498\")").0.is_none());
+        assert!(parse(&format!("\"{}\" joined team \"Red\"", RED)).0.is_none());
+        assert!(parse(&format!("\"{}\" triggered \"damage\"", RED)).0.is_none());
+        assert!(parse("").0.is_none());
+    }
 
-pub fn get_event_game_over(trimmed_line: &str, timestamp: i64, _players: &mut Vec<Player>) -> Option<Event> {
-    if !GAME_OVER.is_match(trimmed_line) {
-        return None;
+    #[test]
+    fn log_info() {
+        let mut info = LogInfo::default();
+        assert_eq!(info.describe(), "");
+        let update = |info: &mut LogInfo, l: &str| info.update(&format!("L 08/19/2021 - 12:09:50: {}", l));
+
+        assert!(update(&mut info, "Log file started (file \"logs/L0819011.log\") (game \"/home/tf2/tf\") (version \"6630498\")"));
+        assert_eq!(info.started, Some(1629374990));
+        assert_eq!(info.file.as_deref(), Some("logs/L0819011.log"));
+        assert_eq!(info.game.as_deref(), Some("/home/tf2/tf"));
+        assert_eq!(info.version.as_deref(), Some("6630498"));
+        assert_eq!(info.describe(), "2021-08-19 12:09");
+
+        // A log that's been rotated starts again, but the first start is the one that counts.
+        assert!(info.update("L 08/19/2021 - 13:00:00: Log file started (file \"logs/L0819012.log\")"));
+        assert_eq!(info.started, Some(1629374990));
+        assert_eq!(info.file.as_deref(), Some("logs/L0819012.log"));
+
+        assert!(update(&mut info, "Loading map \"koth_product_rc8\""));
+        assert!(update(&mut info, "Started map \"koth_product_final\" (CRC \"a8b7d2d4f8c1f9a0ef4b9b0e7b2d51f3\")"));
+        assert_eq!(info.map.as_deref(), Some("koth_product_final"));
+
+        assert!(update(&mut info, "server_cvar: \"mp_timelimit\" \"30\""));
+        assert!(update(&mut info, "server_cvar: \"mp_winlimit\" \"3\""));
+        assert!(update(&mut info, "server_cvar: \"mp_winlimit\" \"5\""));
+        assert!(update(&mut info, "server_cvar: \"mp_maxrounds\" \"0\""));
+        assert!(update(&mut info, "server_cvar: \"sv_password\" \"***PROTECTED***\""));
+        assert_eq!(info.cvars.len(), 4);
+        assert_eq!(info.cvars["mp_winlimit"], "5");
+        // Unset limits are left out.
+        assert_eq!(info.describe(), "koth_product_final, 2021-08-19 12:09, mp_timelimit 30, mp_winlimit 5");
+
+        assert!(!update(&mut info, "server_cvar: \"mp_timelimit\""));
+        assert!(!update(&mut info, "Loading map \"koth_"));
+        assert!(!update(&mut info, "World triggered \"Round_Start\""));
+        assert!(!info.update("Log file started"));
+        assert_eq!(info.describe(), "koth_product_final, 2021-08-19 12:09, mp_timelimit 30, mp_winlimit 5");
     }
 
-    Some(Event::new(
-        timestamp,
-        EventType::GameOver,
-    ))
+    fn at(timestamp: i64, event: EventType) -> Event {
+        Event::new(timestamp, event)
+    }
+
+    fn killed(timestamp: i64) -> Event {
+        at(timestamp, EventType::Kill(KillEvent { attacker: 0, victim: 1, weapon: "scattergun".to_owned(), headshot: false, backstab: false }))
+    }
+
+    #[test]
+    fn matches_split_by_gap() {
+        let events = [
+            at(0, EventType::RoundStart), killed(50), at(100, EventType::RoundWin(Team::Red)),
+            // The server sat empty for a while, then another match was played.
+            at(100 + MATCH_GAP + 1, EventType::RoundStart), killed(500), at(600, EventType::RoundWin(Team::Blu)), at(610, EventType::GameOver),
+        ];
+        let matches = find_matches(&events);
+        assert_eq!(matches.len(), 2);
+        assert_eq!((matches[0].events.clone(), matches[0].ended, matches[0].rounds), (0..3, MatchEnd::Gap, 1));
+        assert_eq!((matches[0].start, matches[0].end), (0, 100));
+        assert_eq!((matches[1].events.clone(), matches[1].ended, matches[1].rounds), (3..7, MatchEnd::GameOver, 1));
+        assert_eq!((matches[1].start, matches[1].end), (100 + MATCH_GAP + 1, 610));
+
+        // Just under the gap is still the same match.
+        let events = [at(0, EventType::RoundStart), killed(MATCH_GAP), at(MATCH_GAP + 10, EventType::GameOver)];
+        let matches = find_matches(&events);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].ended, MatchEnd::GameOver);
+    }
+
+    #[test]
+    fn restart_within_gap() {
+        let events = [
+            at(0, EventType::RoundStart), killed(30), at(60, EventType::TournamentRestart),
+            at(120, EventType::RoundStart), killed(200), at(300, EventType::RoundWin(Team::Red)), at(300, EventType::GameOver),
+            killed(320),
+        ];
+        let matches = find_matches(&events);
+        assert_eq!(matches.len(), 2);
+        assert_eq!((matches[0].events.clone(), matches[0].ended, matches[0].rounds), (0..2, MatchEnd::Restart, 1));
+        assert_eq!((matches[1].events.clone(), matches[1].ended, matches[1].rounds), (3..7, MatchEnd::GameOver, 1));
+
+        // Only the player's events from the first "Round_Start" up to "Game_Over" are kept.
+        let kept: Vec<i64> = filter_events_for(&[0], &events[3..]).unwrap().iter().map(|e| e.timestamp).collect();
+        assert_eq!(kept, [120, 200, 300]);
+    }
+
+    #[test]
+    fn splitter_keeps_one_match() {
+        let events = [
+            at(0, EventType::RoundStart), killed(30), at(60, EventType::TournamentRestart),
+            at(120, EventType::RoundStart), killed(200), at(300, EventType::GameOver),
+            at(400, EventType::RoundStart), killed(450),
+        ];
+        let split = |selected: Option<usize>| {
+            let mut splitter = MatchSplitter::new(selected);
+            for event in &events {
+                splitter.push(event.clone());
+                // No more than the match in progress and the kept one are ever held.
+                assert!(splitter.current.len() + splitter.kept.as_ref().map_or(0, |(_, e)| e.len()) <= 5);
+            }
+            let (matches, kept) = splitter.finish();
+            assert_eq!(matches.len(), 3);
+            kept.map(|(idx, events)| (idx, events.iter().map(|e| e.timestamp).collect::<Vec<_>>()))
+        };
+
+        // The first match played to the end, not the pregame before it or the one the log stopped in.
+        assert_eq!(split(None), Some((1, vec![120, 200, 300])));
+        assert_eq!(split(Some(0)), Some((0, vec![0, 30])));
+        assert_eq!(split(Some(2)), Some((2, vec![400, 450])));
+        assert_eq!(split(Some(3)), None);
+    }
+
+    #[test]
+    fn no_rounds() {
+        let events = [killed(0), killed(10), at(20, EventType::GameOver)];
+        assert!(find_matches(&events).is_empty());
+        assert!(find_rounds(&events).is_empty());
+
+        let e = filter_events_for(&[0], &events).err().unwrap();
+        assert_eq!(e.exit_code(), 3);
+        assert!(filter_events_for(&[0], &[]).is_err());
+        let players = vec![get_player("a<1><[U:1:1]><Red>").unwrap(), get_player("b<2><[U:1:2]><Blue>").unwrap()];
+        assert!(filter_events(&players, &events).is_err());
+    }
+
+    fn left(timestamp: i64, player: usize) -> Event {
+        at(timestamp, EventType::Disconnected(DisconnectedEvent { player, reason: "Disconnect by user.".to_owned() }))
+    }
+
+    fn joined(timestamp: i64, player: usize) -> [Event; 3] {
+        [
+            at(timestamp, EventType::Connected(ConnectedEvent { player, address: "10.0.0.5:27005".to_owned() })),
+            at(timestamp + 1, EventType::Validated(ValidatedEvent { player })),
+            at(timestamp + 10, EventType::Entered(EnteredEvent { player })),
+        ]
+    }
+
+    #[test]
+    fn absences_never_back() {
+        let events = [at(0, EventType::RoundStart), killed(10), left(20, 0), at(100, EventType::GameOver)];
+        assert_eq!(absences(0, &events), [(20, 100)]);
+        assert!(absences(1, &events).is_empty());
+    }
+
+    #[test]
+    fn absences_reconnect() {
+        let mut events = vec![at(0, EventType::RoundStart), killed(10), left(20, 0)];
+        events.extend(joined(40, 0));
+        events.extend([killed(60), left(70, 0)]);
+        // Taking part in anything counts as being back, even if the log missed them entering the game.
+        events.extend([killed(80), at(100, EventType::GameOver)]);
+        assert_eq!(absences(0, &events), [(20, 50), (70, 80)]);
+    }
+
+    #[test]
+    fn absences_from_start() {
+        // Already on the server when the log started, so there's no "connected" line.
+        let events = [killed(10), at(20, EventType::RoundStart), killed(30), at(100, EventType::GameOver)];
+        assert!(absences(0, &events).is_empty());
+        assert!(absences(1, &events).is_empty());
+
+        // Joined part way through.
+        let mut events = vec![at(0, EventType::RoundStart)];
+        events.extend(joined(30, 0));
+        events.extend([killed(60), at(100, EventType::GameOver)]);
+        assert_eq!(absences(0, &events), [(0, 40)]);
+        assert!(absences(1, &events).is_empty());
+        assert!(absences(0, &[]).is_empty());
+    }
+
+    // The counts from before lines were parsed in a single pass, for the events that parser knew about.
+    #[test]
+    fn same_as_before() {
+        let file = File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/l0819011.log")).unwrap();
+        let mut counts = BTreeMap::new();
+        let (players, _, lines) = read_log(BufReader::new(file), |event, _| {
+            // Team chat wasn't parsed yet.
+            let name = match event.event {
+                EventType::Say(SayEvent { team_only: true, .. }) => "say_team",
+                _ => event.event.name(),
+            };
+            *counts.entry(name).or_insert(0) += 1;
+        })
+        .unwrap();
+
+        assert_eq!(lines, 23177);
+        assert_eq!(players.iter().filter(|p| matches!(p.team, Team::Red | Team::Blu)).count(), 18);
+        let expected = [
+            ("change_class", 38), ("charge_deployed", 21), ("damage", 7614), ("fired", 5470), ("game_over", 1), ("heal", 3038),
+            ("hit", 2531), ("kill", 639), ("medic_death", 49), ("point_captured", 21), ("round_overtime", 4), ("round_start", 6),
+            ("round_win", 5), ("say", 72), ("spawn", 840),
+        ];
+        for (name, count) in expected {
+            assert_eq!(counts.get(name), Some(&count), "{}", name);
+        }
+    }
 }
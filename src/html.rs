@@ -0,0 +1,327 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use raqote::SolidSource;
+use serde_json::json;
+
+use crate::draw::*;
+use crate::events::*;
+use crate::summary::*;
+use crate::Player;
+
+const STYLE: &str = r#"
+body { background: BG; color: FG; font-family: monospace; margin: 20px; }
+h1 { font-size: 18px; margin: 0 0 10px 0; }
+#tabs button { background: none; color: FG; border: 1px solid FG; font-family: monospace; padding: 4px 10px; margin: 0 4px 10px 0; cursor: pointer; opacity: 0.5; }
+#tabs button.active { opacity: 1; }
+#graph { position: relative; }
+#timeline { width: 100%; height: 60vh; cursor: grab; display: block; }
+#timeline path { vector-effect: non-scaling-stroke; }
+#tooltip { position: absolute; display: none; pointer-events: none; background: BG; border: 1px solid FG; padding: 6px; white-space: pre; }
+#key span { display: inline-block; margin-right: 16px; }
+#key i { display: inline-block; width: 24px; height: 3px; vertical-align: middle; margin-right: 6px; }
+#highlights li { cursor: pointer; }
+table { border-collapse: collapse; margin-top: 10px; }
+th, td { border-bottom: 1px solid DMG; padding: 4px 10px; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+"#;
+
+const SCRIPT: &str = r#"
+const NS = 'http://www.w3.org/2000/svg';
+const svg = document.getElementById('timeline');
+const tooltip = document.getElementById('tooltip');
+const base = data.height * 0.5;
+
+// Events, grouped into one path per colour.
+const paths = {};
+for (const [x, from, to, cap, color] of data.lines) {
+    const y1 = base - from * data.scale;
+    const y2 = base - to * data.scale;
+    let d = 'M' + x + ' ' + y1 + 'V' + y2;
+    if (cap) {
+        d += 'M' + (x - 3) + ' ' + y2 + 'H' + (x + 3);
+    }
+    paths[color] = (paths[color] || '') + d;
+}
+for (const color in paths) {
+    const path = document.createElementNS(NS, 'path');
+    path.setAttribute('d', paths[color]);
+    path.setAttribute('stroke', color);
+    svg.appendChild(path);
+}
+
+const baseline = document.createElementNS(NS, 'path');
+baseline.setAttribute('d', 'M0 ' + base + 'H' + data.width);
+baseline.setAttribute('stroke', data.fg);
+baseline.setAttribute('stroke-width', 2);
+svg.appendChild(baseline);
+
+for (const h of data.highlights) {
+    const mark = document.createElementNS(NS, 'path');
+    mark.setAttribute('d', 'M' + h.x + ' ' + (data.height - 30) + 'V' + (data.height - 10));
+    mark.setAttribute('stroke', data.fg);
+    svg.appendChild(mark);
+}
+
+// Invisible hover targets, one per batch.
+data.batches.forEach((b, i) => {
+    const left = i > 0 ? (data.batches[i - 1].x + b.x) / 2 : 0;
+    const right = i < data.batches.length - 1 ? (b.x + data.batches[i + 1].x) / 2 : data.width;
+    const rect = document.createElementNS(NS, 'rect');
+    rect.setAttribute('x', left);
+    rect.setAttribute('y', 0);
+    rect.setAttribute('width', right - left);
+    rect.setAttribute('height', data.height);
+    rect.setAttribute('fill', 'transparent');
+    rect.addEventListener('mousemove', (e) => {
+        tooltip.textContent = b.time + '\n' +
+            'events: ' + b.events + '\n' +
+            'damage: ' + b.damage + ' / taken: ' + b.damage_taken + '\n' +
+            'healing: ' + b.healing + ' / received: ' + b.healing_received + '\n' +
+            'kills: ' + b.kills + ' / deaths: ' + b.deaths + '\n' +
+            'score: ' + b.score;
+        const bounds = svg.parentNode.getBoundingClientRect();
+        tooltip.style.left = (e.clientX - bounds.left + 12) + 'px';
+        tooltip.style.top = (e.clientY - bounds.top + 12) + 'px';
+        tooltip.style.display = 'block';
+    });
+    rect.addEventListener('mouseleave', () => tooltip.style.display = 'none');
+    svg.appendChild(rect);
+});
+
+// Zoom and pan along the time axis only.
+let view = [0, data.width];
+function setView(x, w) {
+    w = Math.min(Math.max(w, 10), data.width);
+    x = Math.min(Math.max(x, 0), data.width - w);
+    view = [x, w];
+    svg.setAttribute('viewBox', x + ' 0 ' + w + ' ' + data.height);
+}
+svg.addEventListener('wheel', (e) => {
+    e.preventDefault();
+    const bounds = svg.getBoundingClientRect();
+    const at = view[0] + (e.clientX - bounds.left) / bounds.width * view[1];
+    const w = view[1] * (e.deltaY > 0 ? 1.2 : 1 / 1.2);
+    setView(at - (at - view[0]) * (w / view[1]), w);
+});
+let drag = null;
+svg.addEventListener('mousedown', (e) => drag = { x: e.clientX, view: view[0] });
+window.addEventListener('mouseup', () => drag = null);
+window.addEventListener('mousemove', (e) => {
+    if (drag) {
+        const bounds = svg.getBoundingClientRect();
+        setView(drag.view - (e.clientX - drag.x) / bounds.width * view[1], view[1]);
+    }
+});
+
+// Tabs for the whole match and each round.
+function selectTab(idx) {
+    document.querySelectorAll('#tabs button').forEach((b, i) => b.classList.toggle('active', i === idx));
+    document.querySelectorAll('.summary').forEach((t, i) => t.style.display = i === idx ? '' : 'none');
+    const range = idx === 0 ? [0, data.width] : [data.rounds[idx - 1].x0, data.rounds[idx - 1].x1];
+    document.querySelectorAll('#highlights li').forEach((li) => {
+        const x = parseFloat(li.dataset.x);
+        li.style.display = x >= range[0] && x <= range[1] ? '' : 'none';
+    });
+    setView(range[0], range[1] - range[0]);
+}
+document.querySelectorAll('#tabs button').forEach((b, i) => b.addEventListener('click', () => selectTab(i)));
+document.querySelectorAll('#highlights li').forEach((li) => li.addEventListener('click', () => {
+    setView(parseFloat(li.dataset.x) - 40, 80);
+}));
+selectTab(0);
+"#;
+
+fn css_color(color: SolidSource) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write_summary_table<W: Write>(out: &mut W, summaries: &[PlayerSummary], hidden: bool) -> io::Result<()> {
+    writeln!(out, r#"<table class="summary"{}>"#, if hidden { r#" style="display: none""# } else { "" })?;
+    writeln!(out, "<tr><th>player</th><th>time</th><th>k</th><th>d</th><th>k/d</th><th>dmg</th><th>dpm</th><th>dt</th><th>heal</th><th>airshots</th><th>headshots</th><th>backstabs</th><th>medic kills</th><th>drops</th></tr>")?;
+    for s in summaries {
+        writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td><td>{}</td><td>{:.0}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&s.name), format_game_time(s.seconds), s.kills, s.deaths, s.kd(), s.damage, s.dpm(), s.damage_taken, s.healing,
+            s.airshots, s.headshots, s.backstabs, s.medic_kills, s.drops,
+        )?;
+    }
+    writeln!(out, "</table>")
+}
+
+// A single HTML file with the timeline, highlights and summaries embedded, so it can be shared as is.
+pub fn write_report(
+    filtered: &FilteredEvents,
+    all: &[FilteredEvents],
+    players: &[Player],
+    rounds: &[Round],
+    batching: i64,
+    style: &Style,
+    filename: &str,
+) -> io::Result<()> {
+    println!("Making report for player: {}, batching: {}", filtered.player.name, batching);
+    let player_id = players.iter().position(|i| i.id == filtered.player.id).unwrap();
+
+    let timeline = Timeline::build(&filtered.events, &[player_id], batching, &style.canvas);
+    let (theme, canvas) = (&style.theme, &style.canvas);
+    let match_start = timeline.match_start;
+
+    let lines: Vec<_> = timeline.lines.lines.iter().map(|l| {
+        json!([l.x, l.from_y, l.to_y, l.cap, css_color(l.kind.color(theme))])
+    }).collect();
+    let batches: Vec<_> = timeline.batches.iter().map(|b| {
+        json!({
+            "x": b.x,
+            "time": format_game_time(b.timestamp - match_start),
+            "events": b.events,
+            "damage": b.damage,
+            "damage_taken": b.damage_taken,
+            "healing": b.healing,
+            "healing_received": b.healing_received,
+            "kills": b.kills,
+            "deaths": b.deaths,
+            "score": b.score,
+        })
+    }).collect();
+    let highlights: Vec<_> = timeline.highlights.iter().map(|h| json!({ "x": h.x })).collect();
+    let round_ranges: Vec<_> = rounds.iter().map(|r| {
+        json!({ "x0": timeline.x_at(r.start), "x1": timeline.x_at(r.end) })
+    }).collect();
+
+    let data = json!({
+        "width": canvas.width,
+        "height": canvas.graph_height(),
+        "scale": timeline.lines.global_y_scale,
+        "fg": css_color(theme.fg),
+        "lines": lines,
+        "batches": batches,
+        "highlights": highlights,
+        "rounds": round_ranges,
+    });
+    // Keep player names from closing the script tag early.
+    let data = data.to_string().replace("</", "<\\/");
+
+    let css = STYLE
+        .replace("BG", &css_color(theme.bg))
+        .replace("FG", &css_color(theme.fg))
+        .replace("DMG", &css_color(theme.damage));
+
+    let file = File::create(filename)?;
+    let mut out = BufWriter::new(file);
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, r#"<html><head><meta charset="utf-8"><title>{}</title>"#, escape(&filtered.player.name))?;
+    writeln!(out, "<style>{}</style></head><body>", css)?;
+    writeln!(
+        out,
+        "<h1>Player: {}, batching: {}s, scale: {:.2}</h1>",
+        escape(&filtered.player.name), batching, timeline.lines.global_y_scale
+    )?;
+    if let Some(caption) = &style.caption {
+        writeln!(out, "<h2>{}</h2>", escape(caption))?;
+    }
+
+    write!(out, r#"<div id="tabs"><button>match</button>"#)?;
+    for idx in 0..rounds.len() {
+        write!(out, "<button>round {}</button>", idx + 1)?;
+    }
+    writeln!(out, "</div>")?;
+
+    writeln!(
+        out,
+        r#"<div id="graph"><svg id="timeline" xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}" preserveAspectRatio="none" fill="none"></svg><div id="tooltip"></div></div>"#,
+        canvas.width, canvas.graph_height()
+    )?;
+
+    write!(out, r#"<div id="key">"#)?;
+    for (kind, label) in KEY.iter() {
+        write!(out, r#"<span><i style="background: {}"></i>{}</span>"#, css_color(kind.color(theme)), label)?;
+    }
+    writeln!(out, "</div>")?;
+
+    writeln!(out, r#"<h2>Highlights</h2><ol id="highlights" start="0">"#)?;
+    for highlight in &timeline.highlights {
+        writeln!(
+            out,
+            r#"<li data-x="{}">{} tick={}</li>"#,
+            highlight.x, format_game_time(highlight.timestamp - match_start), highlight.tick
+        )?;
+    }
+    writeln!(out, "</ol>")?;
+
+    writeln!(out, "<h2>Summary</h2>")?;
+    let (from, to) = (match_start, timeline.end);
+    write_summary_table(&mut out, &summarize(all, from, to), false)?;
+    for round in rounds {
+        write_summary_table(&mut out, &summarize(all, round.start, round.end), true)?;
+    }
+
+    writeln!(out, "<script>const data = {};{}</script>", data, SCRIPT)?;
+    writeln!(out, "</body></html>")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::commands::{LogSource, ParsedLog};
+    use crate::download::LogsTf;
+    use crate::theme::Theme;
+
+    #[test]
+    fn report() {
+        let log = concat!(env!("CARGO_MANIFEST_DIR"), "/l0819011.log");
+        let log = ParsedLog::load(&LogSource::File(log.to_owned()), &LogsTf::new(None, true, false), None).unwrap();
+        let style = Style { theme: Theme::dark(), canvas: Canvas::default(), font: load_font(None).unwrap(), caption: Some("koth_product_final".to_owned()), chat: false };
+        let path = std::env::temp_dir().join(format!("log-grapher-report-{}.html", std::process::id()));
+
+        let player = log.filtered.iter().find(|f| f.player.id == "[U:1:83248160]").unwrap();
+        write_report(player, &log.filtered, &log.players, &log.rounds, 10, &style, &path.to_string_lossy()).unwrap();
+        let html = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Everything is inline, so the file works offline and can be passed around on its own. The svg namespace is
+        // only a name, it's never fetched.
+        assert_eq!(html.matches("http").count(), html.matches("http://www.w3.org/2000/svg").count());
+        for external in ["src=", "href=", "<link", "@import", "url("] {
+            assert!(!html.contains(external), "{}", external);
+        }
+        assert_eq!(html.matches("<script>").count(), 1);
+        assert!(html.contains("<title>oh no</title>"));
+        assert!(html.contains("<h2>koth_product_final</h2>"));
+
+        // A single series, the player's own timeline.
+        let timeline = Timeline::build(&player.events, &[log.players.iter().position(|p| p.id == player.player.id).unwrap()], 10, &style.canvas);
+        let data = &html[html.find("const data = ").unwrap() + 13..html.find(";\nconst NS").unwrap()];
+        let data: serde_json::Value = serde_json::from_str(data).unwrap();
+        assert_eq!(data["batches"].as_array().unwrap().len(), timeline.batches.len());
+        assert_eq!(data["lines"].as_array().unwrap().len(), timeline.lines.lines.len());
+        assert_eq!(data["rounds"].as_array().unwrap().len(), log.rounds.len());
+
+        // Every highlight is listed.
+        assert!(!timeline.highlights.is_empty());
+        assert_eq!(data["highlights"].as_array().unwrap().len(), timeline.highlights.len());
+        assert_eq!(html.matches("<li data-x=").count(), timeline.highlights.len());
+
+        // A summary of the match, then one for each round, each with a row for everyone who played.
+        assert_eq!(html.matches(r#"<table class="summary""#).count(), log.rounds.len() + 1);
+        let summaries = summarize(&log.filtered, timeline.match_start, timeline.end);
+        let table = &html[html.find("<h2>Summary</h2>").unwrap()..];
+        let table = &table[..table.find("</table>").unwrap()];
+        assert_eq!(table.matches("<tr><td>").count(), summaries.len());
+        for s in &summaries {
+            assert!(table.contains(&format!("<tr><td>{}</td>", escape(&s.name))), "{}", s.name);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use regex::Regex;
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+pub mod commands;
+mod download;
+mod draw;
+pub mod error;
+pub mod events;
+mod html;
+mod live;
+mod mode;
+mod summary;
+mod theme;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Team {
+    Red,
+    Blu,
+    Spectator,
+    Console,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Player {
+    name: String,
+    #[serde(rename = "steamid")]
+    id: String,
+    team: Team,
+    // Any other names the player went by during the log.
+    aliases: Vec<String>,
+    //class: Class,
+}
+
+const STEAMID64_BASE: u64 = 76561197960265728;
+
+impl Player {
+    pub fn steamid64(&self) -> Option<u64> {
+        steamid64(&self.id)
+    }
+}
+
+// [U:1:83248160] -> 76561198043513888
+pub fn steamid64(steamid3: &str) -> Option<u64> {
+    let account = steamid3.strip_prefix("[U:1:")?.strip_suffix(']')?;
+    Some(STEAMID64_BASE + account.parse::<u64>().ok()?)
+}
+
+// 76561198043513888 -> [U:1:83248160]
+pub fn steamid3(steamid64: &str) -> Option<String> {
+    let account = steamid64.parse::<u64>().ok()?.checked_sub(STEAMID64_BASE)?;
+    Some(format!("[U:1:{}]", account))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Class {
+    Scout,
+    Soldier,
+    Pyro,
+    Demoman,
+    Heavy,
+    Engineer,
+    Medic,
+    Sniper,
+    Spy,
+    Unknown,
+}
+
+// *Sexier Turtle*<9><*[U:1:242326504]*><*Blue*>, with no team (or Unassigned) until they've joined one.
+lazy_static! {
+    static ref PLAYER: Regex = Regex::new(r#"^(?P<name>.{1,80}?)<\d{1,4}><(?P<steamid>.{1,40})><(?P<team>Red|Blue|Spectator|Unassigned|Console|unknown|)>"#).unwrap();
+}
+
+fn get_player(player_str: &str) -> Option<Player> {
+    let captures = PLAYER.captures(player_str)?;
+    let name = captures.name("name").unwrap().as_str();
+    let id = captures.name("steamid").unwrap().as_str();
+    let team = get_team(captures.name("team").unwrap().as_str());
+
+    Some(Player {
+        name: name.to_owned(),
+        id: id.to_owned(),
+        team,
+        aliases: vec![],
+    })
+}
+
+fn get_team(team: &str) -> Team {
+    match team {
+        "Red" => Team::Red,
+        "Blue" => Team::Blu,
+        // Yet to pick a team, which is as good as spectating.
+        "Spectator" | "Unassigned" => Team::Spectator,
+        "Console" => Team::Console,
+        _ => Team::Unknown,
+    }
+}
+
+// None if it doesn't look like a player, so the line can be skipped.
+fn get_or_insert_player_pos(player_slice: &str, players: &mut Vec<Player>) -> Option<usize> {
+    match players.iter().position(|p| player_slice.contains(&p.id)) {
+        Some(pos) => {
+            update_player(&mut players[pos], player_slice);
+            Some(pos)
+        }
+        None => {
+            players.push(get_player(player_slice)?);
+            Some(players.len()-1)
+        }
+    }
+}
+
+// Players can change name or team part way through, so only parse the slice again when something looks different.
+fn update_player(player: &mut Player, player_slice: &str) {
+    let same_name = player_slice
+        .strip_prefix(player.name.as_str())
+        .is_some_and(|rest| rest.starts_with('<'));
+    let same_team = match player.team {
+        Team::Red => player_slice.ends_with("<Red>"),
+        Team::Blu => player_slice.ends_with("<Blue>"),
+        _ => false,
+    };
+    if same_name && same_team {
+        return;
+    }
+
+    let Some(seen) = get_player(player_slice) else { return };
+    if seen.name != player.name && !player.aliases.contains(&seen.name) {
+        player.aliases.push(seen.name);
+    }
+    // Everyone starts out unassigned, and may go to spectator at the end, so remember the last team they played on.
+    if matches!(seen.team, Team::Red | Team::Blu) || !matches!(player.team, Team::Red | Team::Blu) {
+        player.team = seen.team;
+    }
+}
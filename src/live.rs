@@ -0,0 +1,336 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::events::*;
+use crate::Player;
+
+// A log that's still being written, parsed a line at a time as the lines arrive. Only the events of the match in
+// progress (and the last one if it wasn't played to the end) are kept, so a server left running doesn't fill memory.
+#[derive(Debug, Default)]
+pub struct LiveLog {
+    pub players: Vec<Player>,
+    pub matches: MatchSplitter,
+    pub info: LogInfo,
+    pub lines: usize,
+}
+
+impl LiveLog {
+    // The event the line was, if it was one.
+    pub fn push_line(&mut self, line: &str) -> Option<EventType> {
+        self.lines += 1;
+        let line = line.trim_end();
+        match parse_line(line, &mut self.players) {
+            Some(event) => {
+                let kind = event.event.clone();
+                self.matches.push(event);
+                Some(kind)
+            }
+            None => {
+                self.info.update(line);
+                None
+            }
+        }
+    }
+
+    // Starts over for the next match, returning the one so far. The LogInfo carries over, as the server only logs it when the map changes.
+    pub fn take(&mut self) -> Self {
+        let info = self.info.clone();
+        std::mem::replace(self, Self { info, ..Self::default() })
+    }
+}
+
+// srcds sends each line in its own packet: four 0xff bytes, then 'R' and the line, or with sv_logsecret set,
+// 'S', the secret and the line. Anything else, or a packet with the wrong secret, is ignored.
+pub fn parse_packet(packet: &[u8], secret: Option<&str>) -> Option<String> {
+    let packet = packet.strip_prefix(b"\xff\xff\xff\xff")?;
+    let line = match packet.split_first()? {
+        (b'R', line) => {
+            if secret.is_some() {
+                return None;
+            }
+            line
+        }
+        (b'S', rest) => {
+            let start = rest.windows(2).position(|w| w == b"L ")?;
+            let (given, line) = rest.split_at(start);
+            if secret.is_some_and(|secret| secret.as_bytes() != given) {
+                return None;
+            }
+            line
+        }
+        _ => return None,
+    };
+
+    let line = String::from_utf8_lossy(line);
+    Some(line.trim_end_matches(['\0', '\n', '\r']).to_owned())
+}
+
+// What LogFollower found on its last look at the directory.
+pub enum Tail {
+    Line(String),
+    // Now following a new log, e.g. after a map change.
+    Rotated(PathBuf),
+    // Nothing new has been written yet.
+    Idle,
+}
+
+// Follows the newest L*.log in a server's logs directory as srcds writes it, moving on to the next one when the log
+// rotates. Only whole lines are returned, so a line that's still being written waits for the next look.
+pub struct LogFollower {
+    dir: PathBuf,
+    file: Option<(PathBuf, BufReader<File>)>,
+    partial: Vec<u8>,
+}
+
+impl LogFollower {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), file: None, partial: vec![] }
+    }
+
+    pub fn next(&mut self) -> io::Result<Tail> {
+        if let Some((path, reader)) = &mut self.file {
+            // Deleted or moved away, e.g. by a log rotation script. What was already written can still be read.
+            let deleted = match fs::metadata(&*path) {
+                Ok(metadata) => {
+                    // Truncated rather than rotated, so start it again.
+                    if metadata.len() < reader.stream_position()? {
+                        reader.seek(SeekFrom::Start(0))?;
+                        self.partial.clear();
+                    }
+                    false
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => true,
+                Err(e) => return Err(e),
+            };
+
+            if reader.read_until(b'\n', &mut self.partial)? > 0 && self.partial.ends_with(b"\n") {
+                let line = String::from_utf8_lossy(&self.partial).into_owned();
+                self.partial.clear();
+                return Ok(Tail::Line(line));
+            }
+            if deleted {
+                self.file = None;
+            }
+        }
+
+        // Only move on once everything in the current log has been read. Until there's a log to follow, keep waiting.
+        let newest = match newest_log(&self.dir) {
+            Ok(newest) => newest,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        match newest {
+            Some(newest) if self.file.as_ref().is_none_or(|(path, _)| *path != newest) => {
+                let file = match File::open(&newest) {
+                    Ok(file) => file,
+                    // Gone again before it could be opened.
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Tail::Idle),
+                    Err(e) => return Err(e),
+                };
+                self.file = Some((newest.clone(), BufReader::new(file)));
+                self.partial.clear();
+                Ok(Tail::Rotated(newest))
+            }
+            _ => Ok(Tail::Idle),
+        }
+    }
+}
+
+// srcds names its logs L<month><day><number>.log, but they're compared by when they were written as the names wrap around.
+fn newest_log(dir: &Path) -> io::Result<Option<PathBuf>> {
+    let mut newest: Option<(SystemTime, PathBuf)> = None;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !(name.starts_with('L') && name.ends_with(".log")) {
+            continue;
+        }
+
+        let modified = entry.metadata()?.modified()?;
+        let path = entry.path();
+        if newest.as_ref().is_none_or(|(time, newest)| (modified, &path) > (*time, newest)) {
+            newest = Some((modified, path));
+        }
+    }
+
+    Ok(newest.map(|(_, path)| path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    use super::*;
+
+    const LOG: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/l0819011.log");
+
+    fn packet(line: &str, secret: Option<&str>) -> Vec<u8> {
+        let mut packet = b"\xff\xff\xff\xff".to_vec();
+        match secret {
+            Some(secret) => {
+                packet.push(b'S');
+                packet.extend_from_slice(secret.as_bytes());
+            }
+            None => packet.push(b'R'),
+        }
+        packet.extend_from_slice(line.as_bytes());
+        packet.push(b'\0');
+        packet
+    }
+
+    // Each line is sent as srcds would and received before sending the next, so none are dropped.
+    fn listen(secret: Option<&str>, sent_with: impl Fn(usize) -> Option<&'static str>) -> LiveLog {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut live = LiveLog::default();
+        let mut buf = [0; 65536];
+        for (idx, line) in fs::read_to_string(LOG).unwrap().lines().enumerate() {
+            server.send_to(&packet(line, sent_with(idx)), listener.local_addr().unwrap()).unwrap();
+            let (len, _) = listener.recv_from(&mut buf).unwrap();
+            if let Some(line) = parse_packet(&buf[..len], secret) {
+                live.push_line(&line);
+            }
+        }
+        live
+    }
+
+    fn from_file() -> (Vec<Player>, MatchSplitter, LogInfo, usize) {
+        let mut matches = MatchSplitter::default();
+        let (players, info, lines) = read_log(BufReader::new(File::open(LOG).unwrap()), |event, _| matches.push(event)).unwrap();
+        (players, matches, info, lines)
+    }
+
+    #[test]
+    fn same_as_file() {
+        let (players, matches, info, lines) = from_file();
+        let live = listen(None, |_| None);
+        assert_eq!(live.lines, lines);
+        assert_eq!(format!("{:?}", live.players), format!("{:?}", players));
+        assert_eq!(format!("{:?}", live.matches), format!("{:?}", matches));
+        assert_eq!(format!("{:?}", live.info), format!("{:?}", info));
+    }
+
+    #[test]
+    fn secret() {
+        let (_, matches, _, _) = from_file();
+        let live = listen(Some("hunter2"), |_| Some("hunter2"));
+        assert_eq!(format!("{:?}", live.matches), format!("{:?}", matches));
+
+        // Lines without the secret, or with the wrong one, are dropped.
+        let live = listen(Some("hunter2"), |idx| if idx % 2 == 0 { None } else { Some("hunter3") });
+        assert_eq!(live.lines, 0);
+    }
+
+    #[test]
+    fn malformed_packets() {
+        let line = "L 08/19/2021 - 12:10:16: World triggered \"Round_Start\"";
+        assert_eq!(parse_packet(&packet(line, None), None).as_deref(), Some(line));
+        assert_eq!(parse_packet(&packet(line, Some("hunter2")), Some("hunter2")).as_deref(), Some(line));
+
+        for packet in [
+            &b""[..],
+            b"\xff\xff",
+            b"\xff\xff\xff\xff",
+            b"RL 08/19/2021 - 12:10:16: World triggered \"Round_Start\"",
+            b"\xff\xff\xff\xffXL 08/19/2021 - 12:10:16: World triggered \"Round_Start\"",
+            b"\xff\xff\xff\xffShunter2",
+        ] {
+            assert_eq!(parse_packet(packet, None), None, "{:?}", packet);
+        }
+        assert_eq!(parse_packet(&packet(line, None), Some("hunter2")), None);
+        assert_eq!(parse_packet(&packet(line, Some("hunter3")), Some("hunter2")), None);
+    }
+
+    // Anything that survives parse_packet, however it was cut off, shouldn't stop the log.
+    #[test]
+    fn truncated_packets() {
+        let mut live = LiveLog::default();
+        for line in fs::read_to_string(LOG).unwrap().lines().take(2000) {
+            let packet = packet(line, None);
+            for len in 0..packet.len() {
+                if let Some(line) = parse_packet(&packet[..len], None) {
+                    live.push_line(&line);
+                }
+            }
+        }
+        // Bytes that aren't UTF-8 are replaced.
+        let line = parse_packet(b"\xff\xff\xff\xffRL 08/19/2021 - 12:10:16: \"\xe2\x82<3><[U:1:5]><Red>\" say \"\xff\"", None).unwrap();
+        assert!(matches!(live.push_line(&line), Some(EventType::Say(_))));
+    }
+
+    fn next_line(follower: &mut LogFollower) -> Option<String> {
+        match follower.next().unwrap() {
+            Tail::Line(line) => Some(line),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn follow_deleted_log() {
+        let dir = std::env::temp_dir().join(format!("log-grapher-follow-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let mut follower = LogFollower::new(&dir);
+        assert!(matches!(follower.next().unwrap(), Tail::Idle));
+
+        let first = dir.join("L0819011.log");
+        fs::write(&first, "one\ntwo\n").unwrap();
+        assert!(matches!(follower.next().unwrap(), Tail::Rotated(path) if path == first));
+        assert_eq!(next_line(&mut follower).as_deref(), Some("one\n"));
+
+        // What was written before it was deleted is still read, then it waits for the next log.
+        fs::remove_file(&first).unwrap();
+        assert_eq!(next_line(&mut follower).as_deref(), Some("two\n"));
+        assert!(matches!(follower.next().unwrap(), Tail::Idle));
+        assert!(matches!(follower.next().unwrap(), Tail::Idle));
+
+        // Written again under the same name.
+        fs::write(&first, "three\n").unwrap();
+        assert!(matches!(follower.next().unwrap(), Tail::Rotated(path) if path == first));
+        assert_eq!(next_line(&mut follower).as_deref(), Some("three\n"));
+
+        // The whole directory going away is waited out too.
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(follower.next().unwrap(), Tail::Idle));
+        assert!(matches!(follower.next().unwrap(), Tail::Idle));
+    }
+
+    #[test]
+    fn follow_rotated_log() {
+        let dir = std::env::temp_dir().join(format!("log-grapher-rotate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let (now, minutes) = (SystemTime::now(), |m: u64| Duration::from_secs(m * 60));
+        let write = |name: &str, contents: &str, modified: SystemTime| {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+            path
+        };
+
+        // The most recently written log is followed, whatever it's called, and anything that isn't a log is ignored.
+        write("L0818001.log", "old\n", now - minutes(60));
+        write("L1231001.log", "last year\n", now - minutes(10));
+        let first = write("L0819011.log", "one\n", now - minutes(1));
+        write("L0819011.txt", "not a log\n", now);
+        write("console.log", "not one either\n", now);
+        let mut follower = LogFollower::new(&dir);
+        assert!(matches!(follower.next().unwrap(), Tail::Rotated(path) if path == first));
+        assert_eq!(next_line(&mut follower).as_deref(), Some("one\n"));
+        assert!(matches!(follower.next().unwrap(), Tail::Idle));
+
+        // The map changed: the rest of the old log is read before moving on to the new one.
+        File::options().append(true).open(&first).unwrap().write_all(b"two\n").unwrap();
+        let second = write("L0819012.log", "three\n", now + minutes(1));
+        assert_eq!(next_line(&mut follower).as_deref(), Some("two\n"));
+        assert!(matches!(follower.next().unwrap(), Tail::Rotated(path) if path == second));
+        assert_eq!(next_line(&mut follower).as_deref(), Some("three\n"));
+        assert!(matches!(follower.next().unwrap(), Tail::Idle));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+use crate::events::*;
+use crate::summary::*;
+use crate::{Class, Team};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Sixes,
+    Prolander,
+    Highlander,
+    Ultiduo,
+    BBall,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameMode {
+    Koth,
+    ControlPoints,
+    Payload,
+    Ctf,
+    Unknown,
+}
+
+impl Format {
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Sixes => "6v6",
+            Format::Prolander => "Prolander",
+            Format::Highlander => "Highlander",
+            Format::Ultiduo => "Ultiduo",
+            Format::BBall => "BBall",
+            Format::Unknown => "unknown format",
+        }
+    }
+
+    // Roughly what a player's dpm is over a whole match, for comparing against. Medics and classes that don't fight
+    // much pull the average down, so it's lower than what the combat classes do.
+    pub fn average_dpm(self) -> Option<f32> {
+        match self {
+            Format::Sixes => Some(260.0),
+            Format::Prolander => Some(240.0),
+            Format::Highlander => Some(220.0),
+            Format::Ultiduo => Some(180.0),
+            Format::BBall | Format::Unknown => None,
+        }
+    }
+}
+
+impl GameMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Koth => "KOTH",
+            GameMode::ControlPoints => "CP",
+            GameMode::Payload => "PL",
+            GameMode::Ctf => "CTF",
+            GameMode::Unknown => "unknown mode",
+        }
+    }
+}
+
+// Going by how many players each team had and what they played. Subs and players who only joined for a moment shouldn't
+// count, so only those that did something are included.
+pub fn detect_format(filtered: &[FilteredEvents], events: &[Event]) -> Format {
+    let (from, to) = match (events.first(), events.last()) {
+        (Some(first), Some(last)) => (first.timestamp, last.timestamp),
+        _ => return Format::Unknown,
+    };
+    let active: Vec<(usize, Team)> = filtered
+        .iter()
+        .enumerate()
+        .filter(|(idx, f)| matches!(f.player.team, Team::Red | Team::Blu) && !PlayerSummary::new(f, *idx, from, to).is_empty())
+        .map(|(idx, f)| (idx, f.player.team))
+        .collect();
+    if active.is_empty() {
+        return Format::Unknown;
+    }
+
+    let mut classes = vec![];
+    for event in events {
+        if let EventType::Spawn(SpawnEvent { player, class }) | EventType::ChangeClass(ChangeClassEvent { player, class }) = &event.event {
+            if *class != Class::Unknown && active.iter().any(|(idx, _)| idx == player) && !classes.contains(class) {
+                classes.push(*class);
+            }
+        }
+    }
+
+    // The bigger team, as the other might have been a player short.
+    let players = |team: Team| active.iter().filter(|(_, t)| *t == team).count();
+    match players(Team::Red).max(players(Team::Blu)) {
+        1 | 2 if classes.contains(&Class::Medic) => Format::Ultiduo,
+        1 | 2 => Format::BBall,
+        5 | 6 => Format::Sixes,
+        7 => Format::Prolander,
+        8.. => Format::Highlander,
+        _ => Format::Unknown,
+    }
+}
+
+// Going by the map's prefix if it's known, otherwise by the objectives.
+pub fn detect_mode(map: Option<&str>, events: &[Event]) -> GameMode {
+    if let Some(map) = map {
+        let prefix = map.split('_').next().unwrap_or_default().to_lowercase();
+        match prefix.as_str() {
+            "koth" | "ultiduo" => return GameMode::Koth,
+            "cp" => return GameMode::ControlPoints,
+            "pl" | "plr" => return GameMode::Payload,
+            "ctf" | "bball" => return GameMode::Ctf,
+            _ => (),
+        }
+    }
+
+    if events.iter().any(|e| matches!(e.event, EventType::Flag(_))) {
+        return GameMode::Ctf;
+    }
+
+    let caps: Vec<&PointCapturedEvent> = events
+        .iter()
+        .filter_map(|e| match &e.event {
+            EventType::PointCaptured(pc) => Some(pc),
+            _ => None,
+        })
+        .collect();
+    if caps.is_empty() {
+        return GameMode::Unknown;
+    }
+
+    // KOTH has a single point that both teams capture, while on payload only the attackers ever capture anything.
+    let one_point = caps.iter().all(|pc| pc.cp == caps[0].cp);
+    let one_team = caps.iter().all(|pc| pc.team == caps[0].team);
+    if caps.iter().any(|pc| pc.name.to_lowercase().contains("koth")) || (one_point && !one_team) {
+        GameMode::Koth
+    } else if one_team {
+        GameMode::Payload
+    } else {
+        GameMode::ControlPoints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Player;
+
+    use Class::*;
+
+    // A match where everyone on each team spawned as their class and did some damage, with a spectator and someone on
+    // red who joined but never did anything.
+    fn roster(red: &[Class], blu: &[Class]) -> Format {
+        let teams = red.iter().map(|c| (Team::Red, *c)).chain(blu.iter().map(|c| (Team::Blu, *c)));
+        let mut players: Vec<(Team, Class)> = teams.collect();
+        players.push((Team::Spectator, Unknown));
+        players.push((Team::Red, Scout));
+        let idle = players.len() - 1;
+
+        let players: Vec<Player> = players
+            .iter()
+            .enumerate()
+            .map(|(idx, (team, _))| Player { name: format!("player{}", idx), id: format!("[U:1:{}]", idx), team: *team, aliases: vec![] })
+            .collect();
+        let mut events = vec![Event::new(0, EventType::RoundStart)];
+        for (idx, class) in red.iter().chain(blu).enumerate() {
+            events.push(Event::new(1, EventType::Spawn(SpawnEvent { player: idx, class: *class })));
+        }
+        events.push(Event::new(1, EventType::Spawn(SpawnEvent { player: idle, class: Scout })));
+        for idx in 0..red.len() + blu.len() {
+            let victim = if idx < red.len() { red.len() } else { 0 };
+            let damage = DamageEvent { attacker: idx, victim, damage: 50, weapon: "shotgun".to_owned(), headshot: false, airshot: false };
+            events.push(Event::new(10 + idx as i64, EventType::Damage(damage)));
+        }
+        events.push(Event::new(600, EventType::GameOver));
+
+        detect_format(&filter_events(&players, &events).unwrap(), &events)
+    }
+
+    #[test]
+    fn formats() {
+        let sixes = [Scout, Scout, Soldier, Soldier, Demoman, Medic];
+        assert_eq!(roster(&sixes, &sixes), Format::Sixes);
+        assert_eq!(roster(&sixes, &sixes[1..]), Format::Sixes);
+
+        let prolander = [Scout, Soldier, Pyro, Demoman, Engineer, Medic, Sniper];
+        assert_eq!(roster(&prolander, &prolander), Format::Prolander);
+        assert_eq!(roster(&prolander, &sixes), Format::Prolander);
+
+        let highlander = [Scout, Soldier, Pyro, Demoman, Heavy, Engineer, Medic, Sniper, Spy];
+        assert_eq!(roster(&highlander, &highlander), Format::Highlander);
+        // A player short on both sides is still highlander.
+        assert_eq!(roster(&highlander[1..], &highlander[..8]), Format::Highlander);
+
+        assert_eq!(roster(&[Soldier, Medic], &[Soldier, Medic]), Format::Ultiduo);
+        assert_eq!(roster(&[Soldier, Soldier], &[Demoman, Soldier]), Format::BBall);
+        assert_eq!(roster(&[Scout; 4], &[Scout; 4]), Format::Unknown);
+        assert_eq!(roster(&[], &[]), Format::Unknown);
+        assert_eq!(detect_format(&[], &[]), Format::Unknown);
+    }
+
+    fn captured(team: Team, cp: u32, name: &str) -> Event {
+        Event::new(0, EventType::PointCaptured(PointCapturedEvent { team, cp, name: name.to_owned(), cappers: vec![] }))
+    }
+
+    #[test]
+    fn modes_from_map() {
+        for (map, mode) in [
+            ("koth_product_final", GameMode::Koth),
+            ("ultiduo_baloo_v2", GameMode::Koth),
+            ("cp_process_f12", GameMode::ControlPoints),
+            ("CP_Gullywash_f9", GameMode::ControlPoints),
+            ("pl_upward_f10", GameMode::Payload),
+            ("plr_hightower", GameMode::Payload),
+            ("ctf_ballin_sky", GameMode::Ctf),
+            ("bball_eu_fix", GameMode::Ctf),
+        ] {
+            // The map wins over whatever the objectives look like.
+            assert_eq!(detect_mode(Some(map), &[Event::new(0, EventType::Flag(FlagEvent { player: 0, action: FlagAction::PickedUp }))]), mode, "{}", map);
+            assert_eq!(detect_mode(Some(map), &[]), mode, "{}", map);
+        }
+        assert_eq!(detect_mode(Some("mge_training_v8_beta4b"), &[]), GameMode::Unknown);
+        assert_eq!(detect_mode(Some(""), &[]), GameMode::Unknown);
+    }
+
+    #[test]
+    fn modes_from_events() {
+        let flag = Event::new(0, EventType::Flag(FlagEvent { player: 0, action: FlagAction::Captured }));
+        assert_eq!(detect_mode(None, &[flag]), GameMode::Ctf);
+
+        assert_eq!(detect_mode(None, &[captured(Team::Red, 0, "#koth_viaduct_cap")]), GameMode::Koth);
+        assert_eq!(detect_mode(None, &[captured(Team::Red, 0, "Center"), captured(Team::Blu, 0, "Center")]), GameMode::Koth);
+        assert_eq!(detect_mode(Some("mge_training_v8_beta4b"), &[captured(Team::Blu, 0, "A"), captured(Team::Blu, 1, "B")]), GameMode::Payload);
+        let cp = [captured(Team::Red, 2, "Mid"), captured(Team::Red, 3, "Second"), captured(Team::Blu, 2, "Mid")];
+        assert_eq!(detect_mode(None, &cp), GameMode::ControlPoints);
+        assert_eq!(detect_mode(None, &[Event::new(0, EventType::RoundStart)]), GameMode::Unknown);
+    }
+}
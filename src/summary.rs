@@ -0,0 +1,103 @@
+use crate::events::*;
+
+#[derive(Debug, Clone, Default)]
+pub struct PlayerSummary {
+    pub name: String,
+    pub steamid: String,
+    pub kills: u32,
+    pub deaths: u32,
+    pub damage: u32,
+    pub damage_taken: u32,
+    pub healing: u32,
+    pub airshots: u32,
+    pub headshots: u32,
+    pub backstabs: u32,
+    pub medic_kills: u32,
+    pub drops: u32,
+    pub seconds: i64,
+}
+
+impl PlayerSummary {
+    // Totals for the player's events between `from` and `to` (inclusive). Any time they spent off the server doesn't
+    // count towards their playtime, so dpm isn't dragged down by a crash.
+    pub fn new(filtered: &FilteredEvents, player_id: usize, from: i64, to: i64) -> Self {
+        let away: i64 = filtered.absences.iter().map(|(start, end)| (end.min(&to) - start.max(&from)).max(0)).sum();
+        let mut summary = Self {
+            name: filtered.player.name.clone(),
+            steamid: filtered.player.id.clone(),
+            seconds: to - from - away,
+            ..Default::default()
+        };
+
+        let in_range = filtered.events.iter().filter(|e| e.timestamp >= from && e.timestamp <= to);
+        for event in in_range {
+            match &event.event {
+                EventType::Damage(damage) => {
+                    if damage.attacker == player_id {
+                        summary.damage += damage.damage;
+                        if damage.airshot {
+                            summary.airshots += 1;
+                        }
+                    } else if damage.victim == player_id {
+                        summary.damage_taken += damage.damage;
+                    }
+                }
+                EventType::Heal(heal) if heal.healer == player_id => {
+                    summary.healing += heal.healing;
+                }
+                EventType::Kill(kill) => {
+                    if kill.attacker == player_id {
+                        summary.kills += 1;
+                        if kill.headshot {
+                            summary.headshots += 1;
+                        }
+                        if kill.backstab {
+                            summary.backstabs += 1;
+                        }
+                    } else if kill.victim == player_id {
+                        summary.deaths += 1;
+                    }
+                }
+                EventType::MedicDeath(md) if md.attacker == player_id => {
+                    summary.medic_kills += 1;
+                    if md.drop {
+                        summary.drops += 1;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        summary
+    }
+
+    // Spectators and the console end up in the player list too, but never do anything.
+    pub fn is_empty(&self) -> bool {
+        self.kills == 0 && self.deaths == 0 && self.damage == 0 && self.damage_taken == 0 && self.healing == 0
+    }
+
+    pub fn dpm(&self) -> f32 {
+        if self.seconds <= 0 {
+            return 0.0;
+        }
+
+        self.damage as f32 / (self.seconds as f32 / 60.0)
+    }
+
+    pub fn kd(&self) -> f32 {
+        self.kills as f32 / self.deaths.max(1) as f32
+    }
+}
+
+// Summaries for every player that took part between `from` and `to`, best damage first.
+pub fn summarize(filtered: &[FilteredEvents], from: i64, to: i64) -> Vec<PlayerSummary> {
+    let mut summaries: Vec<PlayerSummary> = filtered
+        .iter()
+        .enumerate()
+        .map(|(player_id, f)| PlayerSummary::new(f, player_id, from, to))
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    summaries.sort_by_key(|s| std::cmp::Reverse(s.damage));
+    summaries
+}
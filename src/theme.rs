@@ -0,0 +1,279 @@
+use std::fs;
+
+use raqote::SolidSource;
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub bg: SolidSource,
+    pub fg: SolidSource,
+    pub round: SolidSource,
+    pub overtime: SolidSource,
+    pub red_team: SolidSource,
+    pub blu_team: SolidSource,
+    pub damage: SolidSource,
+    pub kill: SolidSource,
+    pub headshot_backstab_reflect: SolidSource,
+    pub airshot: SolidSource,
+    pub shot_hit: SolidSource,
+    pub medic_kill: SolidSource,
+    pub medic_drop: SolidSource,
+    pub heal: SolidSource,
+    pub death: SolidSource,
+    // Colours for each player in an overlay graph.
+    pub overlay: Vec<SolidSource>,
+}
+
+const fn rgb(r: u8, g: u8, b: u8) -> SolidSource {
+    SolidSource { r, g, b, a: 255 }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            //bg: rgb(41, 40, 40),
+            bg: rgb(24, 26, 32),
+            fg: rgb(212, 190, 152),
+            round: rgb(60, 62, 70),
+            overtime: rgb(216, 166, 87),
+            red_team: rgb(184, 56, 59),
+            blu_team: rgb(88, 133, 162),
+            damage: rgb(90, 82, 76),
+            kill: rgb(169, 182, 101),
+            headshot_backstab_reflect: rgb(216, 166, 87),
+            airshot: rgb(125, 174, 163),
+            shot_hit: rgb(105, 98, 92),
+            medic_kill: rgb(211, 134, 155),
+            medic_drop: rgb(180, 65, 96),
+            heal: rgb(137, 180, 130),
+            death: rgb(234, 105, 98),
+            overlay: vec![
+                rgb(125, 174, 163),
+                rgb(234, 105, 98),
+                rgb(216, 166, 87),
+                rgb(211, 134, 155),
+                rgb(169, 182, 101),
+                rgb(137, 180, 230),
+                rgb(230, 230, 230),
+                rgb(180, 65, 96),
+            ],
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            bg: rgb(251, 247, 238),
+            fg: rgb(60, 56, 54),
+            round: rgb(213, 206, 192),
+            overtime: rgb(215, 153, 33),
+            red_team: rgb(184, 56, 59),
+            blu_team: rgb(58, 110, 150),
+            damage: rgb(189, 174, 147),
+            kill: rgb(121, 116, 14),
+            headshot_backstab_reflect: rgb(181, 118, 20),
+            airshot: rgb(66, 123, 88),
+            shot_hit: rgb(213, 196, 161),
+            medic_kill: rgb(143, 63, 113),
+            medic_drop: rgb(157, 0, 6),
+            heal: rgb(104, 157, 106),
+            death: rgb(204, 36, 29),
+            overlay: vec![
+                rgb(7, 102, 120),
+                rgb(204, 36, 29),
+                rgb(181, 118, 20),
+                rgb(143, 63, 113),
+                rgb(121, 116, 14),
+                rgb(69, 133, 136),
+                rgb(60, 56, 54),
+                rgb(157, 0, 6),
+            ],
+        }
+    }
+
+    // Based on the Okabe-Ito palette, which stays distinguishable with the common forms of colour blindness.
+    pub fn colorblind() -> Self {
+        Self {
+            bg: rgb(24, 26, 32),
+            fg: rgb(230, 230, 230),
+            round: rgb(70, 72, 80),
+            overtime: rgb(240, 228, 66),
+            red_team: rgb(213, 94, 0),
+            blu_team: rgb(0, 114, 178),
+            damage: rgb(110, 110, 110),
+            kill: rgb(0, 158, 115),
+            headshot_backstab_reflect: rgb(240, 228, 66),
+            airshot: rgb(86, 180, 233),
+            shot_hit: rgb(80, 80, 80),
+            medic_kill: rgb(204, 121, 167),
+            medic_drop: rgb(230, 159, 0),
+            heal: rgb(0, 114, 178),
+            death: rgb(213, 94, 0),
+            overlay: vec![
+                rgb(230, 159, 0),
+                rgb(86, 180, 233),
+                rgb(0, 158, 115),
+                rgb(240, 228, 66),
+                rgb(0, 114, 178),
+                rgb(213, 94, 0),
+                rgb(204, 121, 167),
+                rgb(230, 230, 230),
+            ],
+        }
+    }
+
+    pub fn courtney() -> Self {
+        Self {
+            bg: rgb(21, 25, 39),
+            fg: rgb(69, 75, 94),
+            damage: rgb(126, 134, 160),
+            kill: rgb(184, 189, 204),
+            headshot_backstab_reflect: rgb(235, 253, 175),
+            airshot: rgb(184, 239, 6),
+            shot_hit: rgb(156, 149, 220),
+            medic_kill: rgb(255, 180, 153),
+            medic_drop: rgb(255, 31, 87),
+            heal: rgb(112, 188, 109),
+            death: rgb(183, 22, 51),
+            ..Self::dark()
+        }
+    }
+
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "colorblind" => Some(Self::colorblind()),
+            "courtney" => Some(Self::courtney()),
+            _ => None,
+        }
+    }
+
+    // A theme file has one `key = #rrggbb` per line, applied on top of the dark theme (or whichever
+    // built in theme is named by a `base = name` line). `overlay` takes a comma separated list of colours.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+        let mut theme = Self::dark();
+        for (idx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            let error = |msg: &str| format!("{}:{}: {}", path, idx + 1, msg);
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(error("expected `key = value`")),
+            };
+
+            if key == "base" {
+                theme = Self::named(value).ok_or_else(|| error("unknown base theme"))?;
+                continue;
+            }
+
+            if key == "overlay" {
+                theme.overlay = value
+                    .split(',')
+                    .map(|c| parse_color(c.trim()).ok_or_else(|| error("invalid colour")))
+                    .collect::<Result<_, _>>()?;
+                continue;
+            }
+
+            let color = parse_color(value).ok_or_else(|| error("invalid colour"))?;
+            match key {
+                "bg" => theme.bg = color,
+                "fg" => theme.fg = color,
+                "round" => theme.round = color,
+                "overtime" => theme.overtime = color,
+                "red_team" => theme.red_team = color,
+                "blu_team" => theme.blu_team = color,
+                "damage" => theme.damage = color,
+                "kill" => theme.kill = color,
+                "headshot_backstab_reflect" => theme.headshot_backstab_reflect = color,
+                "airshot" => theme.airshot = color,
+                "shot_hit" => theme.shot_hit = color,
+                "medic_kill" => theme.medic_kill = color,
+                "medic_drop" => theme.medic_drop = color,
+                "heal" => theme.heal = color,
+                "death" => theme.death = color,
+                _ => return Err(error("unknown key")),
+            }
+        }
+
+        if theme.overlay.is_empty() {
+            return Err(format!("{}: overlay needs at least one colour", path));
+        }
+
+        Ok(theme)
+    }
+}
+
+// "#rrggbb"
+fn parse_color(value: &str) -> Option<SolidSource> {
+    let hex = value.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let channel = |idx: usize| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok();
+    Some(rgb(channel(0)?, channel(2)?, channel(4)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `contents` to a theme file of its own and parses it.
+    fn parse(name: &str, contents: &str) -> Result<Theme, String> {
+        let path = std::env::temp_dir().join(format!("log-grapher-theme-{}-{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let path = path.to_string_lossy();
+        let theme = Theme::from_file(&path);
+        fs::remove_file(&*path).unwrap();
+        theme.map_err(|e| e.replace(&*path, "theme"))
+    }
+
+    fn same(a: SolidSource, b: SolidSource) -> bool {
+        (a.r, a.g, a.b, a.a) == (b.r, b.g, b.b, b.a)
+    }
+
+    #[test]
+    fn colours() {
+        let theme = parse("colours", "bg = #000000\n  kill=#A9b665  \n\n// heal = #ffffff\n").unwrap();
+        assert!(same(theme.bg, rgb(0, 0, 0)));
+        assert!(same(theme.kill, rgb(169, 182, 101)));
+        assert!(same(theme.heal, Theme::dark().heal));
+        assert!(same(theme.fg, Theme::dark().fg));
+    }
+
+    #[test]
+    fn base() {
+        let theme = parse("base", "base = light\ndeath = #010203\n").unwrap();
+        assert!(same(theme.bg, Theme::light().bg));
+        assert!(same(theme.death, rgb(1, 2, 3)));
+
+        // Anything before the base is overwritten by it.
+        let theme = parse("base-after", "bg = #010203\nbase = colorblind\n").unwrap();
+        assert!(same(theme.bg, Theme::colorblind().bg));
+    }
+
+    #[test]
+    fn overlay() {
+        let theme = parse("overlay", "overlay = #ff0000, #00ff00,#0000ff\n").unwrap();
+        assert_eq!(theme.overlay.len(), 3);
+        assert!(same(theme.overlay[1], rgb(0, 255, 0)));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("no-equals", "bg #000000").unwrap_err(), "theme:1: expected `key = value`");
+        assert_eq!(parse("unknown-key", "\nbackground = #000000").unwrap_err(), "theme:2: unknown key");
+        assert_eq!(parse("unknown-base", "base = neon").unwrap_err(), "theme:1: unknown base theme");
+        for bad in ["000000", "#00000", "#0000000", "#gg0000", "#ü0000", "red"] {
+            assert_eq!(parse("bad-colour", &format!("// {}\nbg = {}", bad, bad)).unwrap_err(), "theme:2: invalid colour", "{}", bad);
+        }
+        assert_eq!(parse("bad-overlay", "overlay = #ff0000,,#0000ff").unwrap_err(), "theme:1: invalid colour");
+
+        let missing = Theme::from_file("/nonexistent/theme").unwrap_err();
+        assert!(missing.starts_with("/nonexistent/theme: "), "{}", missing);
+    }
+}
@@ -0,0 +1,40 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("log-grapher-batch-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn one_failed() {
+    let dir = output_dir("one-failed");
+    let good = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/small.log");
+    let missing = dir.with_extension("log");
+
+    let batch = Command::new(env!("CARGO_BIN_EXE_log-grapher"))
+        .args(["batch", "--log-file", good, "--log-file"])
+        .arg(&missing)
+        .arg("--output-dir")
+        .arg(&dir)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&batch.stderr);
+    assert_eq!(batch.status.code(), Some(3), "{}", stderr);
+    assert!(stderr.contains("1 of 2 logs"), "{}", stderr);
+
+    let index = fs::read_to_string(dir.join("index.csv")).unwrap();
+    let rows: Vec<&str> = index.lines().collect();
+    assert_eq!(rows.len(), 3, "{}", index);
+    assert!(rows[0].starts_with("log,source,status,"));
+    assert!(rows[1].starts_with(&format!("small,{},ok,", good)), "{}", rows[1]);
+    assert!(rows[2].contains(",failed,"), "{}", rows[2]);
+    assert!(dir.join("small/summary.csv").exists());
+
+    let failures = fs::read_to_string(dir.join("failures.txt")).unwrap();
+    assert_eq!(failures.lines().count(), 1);
+    assert!(failures.contains(&missing.display().to_string()), "{}", failures);
+    fs::remove_dir_all(&dir).unwrap();
+}
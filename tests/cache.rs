@@ -0,0 +1,99 @@
+use std::fs::{self, File};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::{Duration, SystemTime};
+
+use zip::write::{FileOptions, ZipWriter};
+
+// A cache of its own for each test, so the real one is never touched.
+fn cache_home(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("log-grapher-cache-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("log-grapher")).unwrap();
+    dir
+}
+
+fn run(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_log-grapher"))
+        .args(args)
+        .env("XDG_CACHE_HOME", home)
+        .env("HOME", home)
+        .output()
+        .unwrap()
+}
+
+fn put(home: &Path, key: &str, bytes: &[u8], age: Duration) {
+    let path = home.join("log-grapher").join(key);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, bytes).unwrap();
+    File::options().write(true).open(&path).unwrap().set_modified(SystemTime::now() - age).unwrap();
+}
+
+fn zipped_log(log_id: &str) -> Vec<u8> {
+    let log = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/l0819011.log")).unwrap();
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    zip.start_file(format!("log_{}.log", log_id), FileOptions::default()).unwrap();
+    zip.write_all(&log).unwrap();
+    zip.finish().unwrap().into_inner()
+}
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[test]
+fn offline() {
+    let home = cache_home("offline");
+    put(&home, "logs/2956105.zip", &zipped_log("2956105"), Duration::ZERO);
+
+    // Nothing is listening on the base url, so anything not in the cache would fail to download either way.
+    let hit = run(&home, &["inspect", "--log-id", "2956105", "--offline", "--base-url", "http://127.0.0.1:9"]);
+    assert!(hit.status.success(), "{}", String::from_utf8_lossy(&hit.stderr));
+    assert!(String::from_utf8_lossy(&hit.stdout).contains("lines: 23177"));
+
+    let miss = run(&home, &["inspect", "--log-id", "2956106", "--offline", "--base-url", "http://127.0.0.1:9"]);
+    assert_eq!(miss.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&miss.stderr).contains("isn't in the cache and --offline was given"));
+    fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn list() {
+    let home = cache_home("list");
+    put(&home, "logs/1.zip", &[0; 2048], 2 * DAY);
+    put(&home, "meta/1.json", b"{}", DAY);
+
+    let listed = run(&home, &["cache"]);
+    assert!(listed.status.success());
+    let stdout = String::from_utf8_lossy(&listed.stdout);
+    assert!(stdout.contains(&format!("cache: {}", home.join("log-grapher").display())), "{}", stdout);
+    assert!(stdout.contains("files: 2 (2.0 KB)"), "{}", stdout);
+    // Oldest first.
+    let (logs, meta) = (stdout.find("logs/1.zip").unwrap(), stdout.find("meta/1.json").unwrap());
+    assert!(logs < meta);
+    fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn prune() {
+    let home = cache_home("prune");
+    put(&home, "logs/1.zip", &[0; 1024], 10 * DAY);
+    put(&home, "logs/2.zip", &[0; 1024], 3 * DAY);
+    put(&home, "meta/2.json", b"{}", 4 * DAY);
+    put(&home, "logs/3.zip", &[0; 1024], Duration::ZERO);
+    let cached = |key: &str| home.join("log-grapher").join(key).exists();
+
+    assert!(run(&home, &["cache", "--prune", "--older-than", "7"]).status.success());
+    assert!(!cached("logs/1.zip"));
+    assert!(cached("logs/2.zip") && cached("meta/2.json") && cached("logs/3.zip"));
+
+    // Under 1.5 KB means only the newest download is kept.
+    assert!(run(&home, &["cache", "--prune", "--max-size", "0.0015"]).status.success());
+    assert!(!cached("logs/2.zip") && !cached("meta/2.json"));
+    assert!(cached("logs/3.zip"));
+
+    assert!(run(&home, &["cache", "--prune"]).status.success());
+    assert!(!cached("logs/3.zip"));
+
+    assert_eq!(run(&home, &["cache", "--older-than", "7"]).status.code(), Some(2));
+    fs::remove_dir_all(&home).unwrap();
+}
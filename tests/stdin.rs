@@ -0,0 +1,47 @@
+use std::fs;
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
+
+use flate2::write::GzEncoder;
+use zip::write::{FileOptions, ZipWriter};
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+// Exports the log piped in on stdin.
+fn export(log: &[u8]) -> String {
+    let mut export = Command::new(env!("CARGO_BIN_EXE_log-grapher"))
+        .args(["export", "--format", "ndjson", "--log-file", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    export.stdin.take().unwrap().write_all(log).unwrap();
+    let output = export.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn compressed_stdin() {
+    let plain = fs::read(fixture("small.log")).unwrap();
+    // The same as the file, other than where it came from.
+    let expected = fs::read_to_string(fixture("small.ndjson")).unwrap().replacen(r#""id":"small""#, r#""id":"-""#, 1);
+
+    let mut gz = GzEncoder::new(vec![], flate2::Compression::default());
+    gz.write_all(&plain).unwrap();
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    zip.start_file("log_42.log", FileOptions::default()).unwrap();
+    zip.write_all(&plain).unwrap();
+
+    for (compression, log) in [
+        ("plain", plain.clone()),
+        ("gzip", gz.finish().unwrap()),
+        ("zstd", zstd::encode_all(&plain[..], 0).unwrap()),
+        ("zip", zip.finish().unwrap().into_inner()),
+    ] {
+        assert_eq!(export(&log), expected, "{}", compression);
+    }
+}